@@ -0,0 +1,173 @@
+#ifndef RUSTEVAL_H
+#define RUSTEVAL_H
+
+/* Generated with cbindgen from src/capi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Outcome of a call to the C interface.
+typedef enum RustevalStatus {
+  RUSTEVAL_STATUS_OK = 0,
+  // A required pointer argument was NULL.
+  RUSTEVAL_STATUS_NULL_POINTER = 1,
+  // A string argument was not valid UTF-8.
+  RUSTEVAL_STATUS_INVALID_UTF8 = 2,
+  // A numerical argument was out of range (e.g. a relevance outside (0, 1] or an empty box).
+  RUSTEVAL_STATUS_INVALID_ARGUMENT = 3,
+  // The query has no relevant tokens, or has not been evaluated.
+  RUSTEVAL_STATUS_UNKNOWN_QUERY = 4,
+  // The metric name is not one of the computed benchmarks.
+  RUSTEVAL_STATUS_UNKNOWN_METRIC = 5,
+  // There is no value to return, e.g. a mean was requested before any evaluation.
+  RUSTEVAL_STATUS_NO_VALUE = 6,
+  // An internal error occurred; the data object should not be used any further.
+  RUSTEVAL_STATUS_PANIC = 7,
+} RustevalStatus;
+
+// Opaque evaluation object: relevance judgements, results and the metrics computed on them.
+typedef struct RustevalData RustevalData;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates an empty evaluation object, to be released with `rusteval_free`.
+struct RustevalData *rusteval_new(void);
+
+// Releases an evaluation object. Passing NULL is a no-op.
+//
+// # Safety
+// `data` must be NULL or a pointer returned by `rusteval_new` that has not been freed yet.
+void rusteval_free(struct RustevalData *data);
+
+// Only the first `depth` retrieved tokens of each query are taken into account.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`.
+enum RustevalStatus rusteval_set_depth(struct RustevalData *data, uintptr_t depth);
+
+// Relevant tokens with relevance below `min_relevance` are treated as non-relevant.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`.
+enum RustevalStatus rusteval_set_min_relevance(struct RustevalData *data, float min_relevance);
+
+// Maps the relevance grade `grade` to `gain` in graded metrics (nDCG); unmapped grades are their own gain.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`.
+enum RustevalStatus rusteval_set_gain(struct RustevalData *data,
+                                      int32_t grade,
+                                      float gain);
+
+// Adds a relevant bounding box of `w` x `h` pixels with top-left corner (`x`, `y`).
+// `document` may be NULL; `relevance` must lie in (0, 1], use 1.0 for binary relevance.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_relevant_box(struct RustevalData *data,
+                                              const char *query,
+                                              const char *document,
+                                              uint32_t x,
+                                              uint32_t y,
+                                              uint32_t w,
+                                              uint32_t h,
+                                              float relevance);
+
+// Adds a relevant bounding box with an integer relevance `grade`, e.g. 0 to 3 as in graded trec qrels.
+// Tokens with a grade of 0 or less are judged non-relevant, unless a lower minimum relevance is set.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_graded_box(struct RustevalData *data,
+                                            const char *query,
+                                            const char *document,
+                                            uint32_t x,
+                                            uint32_t y,
+                                            uint32_t w,
+                                            uint32_t h,
+                                            int32_t grade);
+
+// Adds a relevant token identified by the string `id`.
+// `document` may be NULL; `relevance` must lie in (0, 1], use 1.0 for binary relevance.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_relevant_string(struct RustevalData *data,
+                                                 const char *query,
+                                                 const char *document,
+                                                 const char *id,
+                                                 float relevance);
+
+// Adds a relevant token identified by the string `id`, with an integer relevance `grade`.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_graded_string(struct RustevalData *data,
+                                               const char *query,
+                                               const char *document,
+                                               const char *id,
+                                               int32_t grade);
+
+// Appends a retrieved bounding box to the result list of `query`.
+// Results must be added from best to worst match; a NaN `score` means no score.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_result_box(struct RustevalData *data,
+                                            const char *query,
+                                            const char *document,
+                                            uint32_t x,
+                                            uint32_t y,
+                                            uint32_t w,
+                                            uint32_t h,
+                                            float score);
+
+// Appends a retrieved token identified by the string `id` to the result list of `query`.
+// Results must be added from best to worst match; a NaN `score` means no score.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+enum RustevalStatus rusteval_add_result_string(struct RustevalData *data,
+                                               const char *query,
+                                               const char *document,
+                                               const char *id,
+                                               float score);
+
+// Computes all metrics for every query with a result list.
+// Fails with `UnknownQuery` if a result list has no relevant tokens.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`.
+enum RustevalStatus rusteval_evaluate(struct RustevalData *data);
+
+// Stores in `value` the metric `metric` (e.g. "ap", "precAt5", "tieAp") of query `query`.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`, strings NUL-terminated
+// and `value` a valid pointer to a float.
+enum RustevalStatus rusteval_query_metric(const struct RustevalData *data,
+                                          const char *query,
+                                          const char *metric,
+                                          float *value);
+
+// Stores in `value` the mean of metric `metric` over all evaluated queries.
+//
+// # Safety
+// `data` must be a live pointer returned by `rusteval_new`, `metric` NUL-terminated
+// and `value` a valid pointer to a float.
+enum RustevalStatus rusteval_mean_metric(const struct RustevalData *data,
+                                         const char *metric,
+                                         float *value);
+
+// A static, NUL-terminated description of `status`.
+const char *rusteval_status_message(enum RustevalStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUSTEVAL_H */
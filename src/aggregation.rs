@@ -0,0 +1,165 @@
+//! Document-level evaluation of word-level runs: which pages contain a keyword, not where.
+//!
+//! The tokens of each query are collapsed into one token per document, a string id holding the
+//! document name. A page is relevant with the highest relevance (or grade) of its relevant words; retrieved
+//! pages are ranked by the first occurrence of one of their words, or by their best scoring word.
+//!
+//! Relevant spans are likewise collapsed into their lines when whole lines are retrieved.
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+use tokens::{RelevantToken, TokenId};
+
+/// How retrieved pages are ranked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DocumentRanking {
+	/// By the rank of their first word in the result list; the page gets the score of that word.
+	FirstOccurrence,
+	/// By the highest score of their words; in rank order if some words have no score.
+	MaxScore,
+}
+
+impl DocumentRanking {
+	pub fn from_name(name: &str) -> Option<DocumentRanking> {
+		match name {
+			"first" => Some(DocumentRanking::FirstOccurrence),
+			"max" => Some(DocumentRanking::MaxScore),
+			_ => None,
+		}
+	}
+}
+
+fn document_token(document: &str) -> RelevantToken {
+	let mut tok = RelevantToken::new();
+	tok.set_tokenid(TokenId::StringId(String::from(document))).set_document(String::from(document));
+	tok
+}
+
+/// The tokens grouped by document, in order of first occurrence; tokens without a document are left out.
+fn by_document(tokens: &[RelevantToken]) -> Vec<(&str, Vec<&RelevantToken>)> {
+	let mut groups: Vec<(&str, Vec<&RelevantToken>)> = Vec::new();
+	let mut positions: HashMap<&str, usize> = HashMap::new();
+	for tok in tokens {
+		let d = match tok.get_document() { Some(d) => d.as_str(), None => continue };
+		let k = *positions.entry(d).or_insert_with( || { groups.push((d, Vec::new())); groups.len() - 1 } );
+		groups[k].1.push(tok);
+	}
+	groups
+}
+
+/// Replaces relevant words by their documents, with the highest relevance and grade of their words.
+pub fn aggregate_relevants(tokens: &mut Vec<RelevantToken>) {
+	let documents: Vec<RelevantToken> = by_document(tokens).into_iter()
+		.map( |(d, words)| {
+			let mut tok = document_token(d);
+			let relevance = words.iter().filter_map( |w| w.relevance ).fold(None, |acc: Option<f32>, r| Some(acc.map_or(r, |a| a.max(r))) );
+			if let Some(r) = relevance { tok.set_relevance(r); }
+			if let Some(g) = words.iter().filter_map( |w| w.get_grade() ).max() { tok.set_grade(g); }
+			tok
+		})
+		.collect();
+	*tokens = documents;
+}
+
+/// Replaces relevant spans by their lines, once per line and document, with the highest relevance and
+/// grade of the spans and of the line itself, if it is relevant too. Other tokens are left as they are.
+pub fn aggregate_spans(tokens: &mut Vec<RelevantToken>) {
+	let mut lines: Vec<RelevantToken> = Vec::new();
+	let mut positions: HashMap<(Option<String>, String), usize> = HashMap::new();
+	for tok in tokens.drain(..) {
+		let line = match tok.id {
+			TokenId::Line(ref l) | TokenId::Span(ref l, _, _) => l.clone(),
+			_ => { lines.push(tok); continue; },
+		};
+		match positions.get(&(tok.get_document().cloned(), line.clone())) {
+			Some(&k) => {
+				let merged = &mut lines[k];
+				merged.relevance = match (merged.relevance, tok.relevance) {
+					(Some(a), Some(b)) => Some(a.max(b)),
+					(a, b) => a.or(b),
+				};
+				merged.grade = merged.grade.max(tok.grade);
+			},
+			None => {
+				positions.insert((tok.get_document().cloned(), line.clone()), lines.len());
+				let mut merged = tok;
+				merged.set_tokenid(TokenId::Line(line));
+				lines.push(merged);
+			},
+		}
+	}
+	*tokens = lines;
+}
+
+/// Replaces a ranked list of words by the ranked list of their documents.
+pub fn aggregate_results(tokens: &mut Vec<RelevantToken>, ranking: DocumentRanking) {
+	let by_score = ranking == DocumentRanking::MaxScore && tokens.iter().all( |tok| tok.get_score().is_some() );
+	let mut documents: Vec<RelevantToken> = by_document(tokens).into_iter()
+		.map( |(d, words)| {
+			let mut tok = document_token(d);
+			let score = if by_score {
+				words.iter().filter_map( |w| w.get_score() ).fold(None, |acc: Option<f32>, s| Some(acc.map_or(s, |a| a.max(s))) )
+			} else {
+				words[0].get_score()
+			};
+			if let Some(s) = score { tok.set_score(s); }
+			tok
+		})
+		.collect();
+	if by_score {
+		// The sort is stable, so pages with the same score stay in order of first occurrence
+		documents.sort_by( |a, b| b.get_score().partial_cmp(&a.get_score()).unwrap_or(Ordering::Equal) );
+	}
+	*tokens = documents;
+}
+
+#[test]
+fn test_document_aggregation() {
+	use rectangles::Rectangle;
+	let word = |document: &str, x: u32, relevance: Option<f32>, score: Option<f32>| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::BoundingBox(Rectangle::from_min_size(x, 10, 50, 20).unwrap()))
+			.set_document(String::from(document));
+		if let Some(r) = relevance { tok.set_relevance(r); }
+		if let Some(s) = score { tok.set_score(s); }
+		tok
+	};
+	let documents = |tokens: &[RelevantToken]| -> Vec<(String, Option<f32>, Option<f32>)> {
+		tokens.iter().map( |t| match t.id {
+			TokenId::StringId(ref d) => (d.clone(), t.relevance, t.get_score()),
+			_ => panic!("Expected a document"),
+		}).collect()
+	};
+	let mut relevants = vec![word("a", 0, Some(0.5), None), word("b", 0, Some(0.6), None), word("a", 100, Some(1.0), None)];
+	aggregate_relevants(&mut relevants);
+	assert_eq!(documents(&relevants), vec![(String::from("a"), Some(1.0), None), (String::from("b"), Some(0.6), None)]);
+
+	let results = vec![word("c", 0, None, Some(0.9)), word("a", 0, None, Some(0.8)), word("b", 0, None, Some(0.7)),
+		word("c", 50, None, Some(0.6)), word("b", 50, None, Some(0.95))];
+	let mut first = results.clone();
+	aggregate_results(&mut first, DocumentRanking::FirstOccurrence);
+	assert_eq!(documents(&first), vec![(String::from("c"), None, Some(0.9)), (String::from("a"), None, Some(0.8)),
+		(String::from("b"), None, Some(0.7))]);
+	let mut best = results.clone();
+	aggregate_results(&mut best, DocumentRanking::MaxScore);
+	assert_eq!(documents(&best), vec![(String::from("b"), None, Some(0.95)), (String::from("c"), None, Some(0.9)),
+		(String::from("a"), None, Some(0.8))]);
+	// Aggregation is idempotent
+	aggregate_results(&mut best, DocumentRanking::MaxScore);
+	aggregate_relevants(&mut relevants);
+	assert_eq!(best.len(), 3);
+	assert_eq!(relevants.len(), 2);
+
+	let span = |document: &str, line: &str, start: u32, relevance: f32| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::Span(String::from(line), start, start)).set_document(String::from(document)).set_relevance(relevance);
+		tok
+	};
+	let mut spans = vec![span("a", "l1", 0, 0.5), word("a", 0, None, None), span("b", "l1", 2, 0.7), span("a", "l1", 3, 0.9)];
+	aggregate_spans(&mut spans);
+	let lines: Vec<(Option<String>, Option<f32>)> = spans.iter().map( |t| match t.id {
+		TokenId::Line(ref l) => (Some(format!("{}_{}", t.get_document().unwrap(), l)), t.relevance),
+		_ => (None, t.relevance),
+	}).collect();
+	assert_eq!(lines, vec![(Some(String::from("a_l1")), Some(0.9)), (None, None), (Some(String::from("b_l1")), Some(0.7))]);
+}
@@ -0,0 +1,389 @@
+//! Binary cache of parsed relevance judgements (`rusteval index`).
+//!
+//! An index file holds the relevant tokens and query instances of a relevance file,
+//! so that they can be loaded without parsing the XML again. Layout, little endian:
+//!
+//! ```text
+//! magic "RVIX" | version u32 | source checksum u64 | source path | payload checksum u64 | payload
+//! payload: #instances u32, (query, token)* | #queries u32, (query, record length u64, record)*
+//! record:  #tokens u32, token*, (match key u64, token position u32)* sorted by key
+//! ```
+//!
+//! Strings are a u32 byte length followed by UTF-8 bytes; checksums are 64-bit FNV-1a.
+//! The record lengths act as a query directory, so that unselected queries are skipped
+//! without being decoded. The match keys of each query (see `match_key`) are loaded as they are,
+//! so that exact matching looks up the relevant tokens of a result instead of scanning them all.
+//! The source path is stored canonicalized, so that the index can be used from any directory.
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+
+use rectangles::Rectangle;
+use polygons::Polygon;
+use retrieval::RetrievalData;
+use tokens::{RelevantToken, TokenId};
+
+pub const INDEX_MAGIC: &[u8] = b"RVIX";
+pub const INDEX_VERSION: u32 = 2;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a hash, continuing from `hash`.
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+	for b in bytes {
+		hash ^= *b as u64;
+		hash = hash.wrapping_mul(FNV_PRIME);
+	}
+	hash
+}
+
+fn put_extent(buf: &mut Vec<u8>, rect: &Rectangle) {
+	buf.push(2);
+	for &v in [rect.min().get_x(), rect.min().get_y(), rect.max().get_x(), rect.max().get_y()].iter() { put_u32(buf, v); }
+}
+
+/// A key of the tokens that match `tok` exactly, i.e. without an overlap threshold: tokens that
+/// match have the same key. Regions are keyed by their extent, lines and spans by their line.
+pub(crate) fn match_key(tok: &RelevantToken) -> u64 {
+	let mut buf = Vec::new();
+	match tok.id {
+		TokenId::NumericId(i) => { buf.push(0); put_u32(&mut buf, i as u32); },
+		TokenId::StringId(ref s) => { buf.push(1); put_str(&mut buf, s); },
+		TokenId::BoundingBox(rect) => put_extent(&mut buf, &rect),
+		TokenId::Polygon(ref poly) => put_extent(&mut buf, &poly.bounding_box()),
+		TokenId::Line(ref line) | TokenId::Span(ref line, _, _) => {
+			buf.push(3);
+			put_str(&mut buf, tok.get_document().map_or("", |d| d.as_str()));
+			put_str(&mut buf, line);
+		},
+	}
+	fnv1a(FNV_OFFSET, &buf)
+}
+
+/// The match keys of `tokens` with their positions, sorted by key.
+pub(crate) fn match_keys(tokens: &[RelevantToken]) -> Vec<(u64, usize)> {
+	let mut keys: Vec<(u64, usize)> = tokens.iter().enumerate().map( |(i, tok)| (match_key(tok), i) ).collect();
+	keys.sort_unstable();
+	keys
+}
+
+/// Checksum of the raw (possibly compressed) contents of a file.
+pub fn file_checksum(filename: &str) -> io::Result<u64> {
+	let mut f = File::open(filename)?;
+	let mut buf = [0u8; 65536];
+	let mut hash = FNV_OFFSET;
+	loop {
+		let n = f.read(&mut buf)?;
+		if n == 0 { return Ok(hash); }
+		hash = fnv1a(hash, &buf[..n]);
+	}
+}
+
+/// Whether `filename` is an index file, judging by its magic bytes.
+pub fn is_index_file(filename: &str) -> bool {
+	let mut magic = [0u8; 4];
+	match File::open(filename) {
+		Ok(mut f) => f.read_exact(&mut magic).is_ok() && magic == INDEX_MAGIC,
+		Err(_) => false,
+	}
+}
+
+fn invalid(msg: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, String::from(msg))
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) { buf.extend_from_slice(&v.to_le_bytes()); }
+fn put_u64(buf: &mut Vec<u8>, v: u64) { buf.extend_from_slice(&v.to_le_bytes()); }
+fn put_f32(buf: &mut Vec<u8>, v: f32) { buf.extend_from_slice(&v.to_le_bytes()); }
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+	put_u32(buf, s.len() as u32);
+	buf.extend_from_slice(s.as_bytes());
+}
+
+const HAS_DOCUMENT: u8 = 1;
+const HAS_TRANSCRIPTION: u8 = 2;
+const HAS_RELEVANCE: u8 = 4;
+const HAS_SCORE: u8 = 8;
+const HAS_GRADE: u8 = 16;
+
+fn put_token(buf: &mut Vec<u8>, tok: &RelevantToken) {
+	match tok.id {
+		TokenId::NumericId(i) => {
+			buf.push(0);
+			put_u32(buf, i as u32);
+		},
+		TokenId::BoundingBox(ref bb) => {
+			buf.push(1);
+			for v in &[bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()] { put_u32(buf, *v); }
+		},
+		TokenId::StringId(ref s) => {
+			buf.push(2);
+			put_str(buf, s);
+		},
+		TokenId::Polygon(ref poly) => {
+			buf.push(3);
+			put_u32(buf, poly.points().len() as u32);
+			for &(x, y) in poly.points() { put_u32(buf, x); put_u32(buf, y); }
+		},
+		TokenId::Line(ref line) => {
+			buf.push(4);
+			put_str(buf, line);
+		},
+		TokenId::Span(ref line, start, end) => {
+			buf.push(5);
+			put_str(buf, line);
+			put_u32(buf, start);
+			put_u32(buf, end);
+		},
+	}
+	let mut flags = 0;
+	if tok.document.is_some() { flags |= HAS_DOCUMENT; }
+	if tok.transcription.is_some() { flags |= HAS_TRANSCRIPTION; }
+	if tok.relevance.is_some() { flags |= HAS_RELEVANCE; }
+	if tok.score.is_some() { flags |= HAS_SCORE; }
+	if tok.grade.is_some() { flags |= HAS_GRADE; }
+	buf.push(flags);
+	if let Some(ref d) = tok.document { put_str(buf, d); }
+	if let Some(ref t) = tok.transcription { put_str(buf, t); }
+	if let Some(r) = tok.relevance { put_f32(buf, r); }
+	if let Some(s) = tok.score { put_f32(buf, s); }
+	if let Some(g) = tok.grade { put_u32(buf, g as u32); }
+}
+
+/// Reads values off a byte slice, failing on truncated input.
+struct Cursor<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+	fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+		if self.bytes.len() - self.pos < n { return Err(invalid("Truncated index file")); }
+		self.pos += n;
+		Ok(&self.bytes[self.pos - n..self.pos])
+	}
+	fn u8(&mut self) -> io::Result<u8> { Ok(self.take(1)?[0]) }
+	fn u32(&mut self) -> io::Result<u32> {
+		let mut b = [0u8; 4]; b.copy_from_slice(self.take(4)?); Ok(u32::from_le_bytes(b))
+	}
+	fn u64(&mut self) -> io::Result<u64> {
+		let mut b = [0u8; 8]; b.copy_from_slice(self.take(8)?); Ok(u64::from_le_bytes(b))
+	}
+	fn f32(&mut self) -> io::Result<f32> {
+		let mut b = [0u8; 4]; b.copy_from_slice(self.take(4)?); Ok(f32::from_le_bytes(b))
+	}
+	fn string(&mut self) -> io::Result<String> {
+		let n = self.u32()? as usize;
+		String::from_utf8(self.take(n)?.to_vec()).map_err( |_| invalid("Invalid string in index file") )
+	}
+	fn token(&mut self) -> io::Result<RelevantToken> {
+		let mut tok = RelevantToken::new();
+		match self.u8()? {
+			0 => {
+				let i = self.u32()?;
+				if i > u16::MAX as u32 { return Err(invalid("Invalid numeric id in index file")); }
+				tok.set_tokenid(TokenId::NumericId(i as u16));
+			},
+			1 => {
+				let (x, y, w, h) = (self.u32()?, self.u32()?, self.u32()?, self.u32()?);
+				let rect = Rectangle::from_min_size(x, y, w, h).map_err( |_| invalid("Invalid bounding box in index file") )?;
+				tok.set_tokenid(TokenId::BoundingBox(rect));
+			},
+			2 => { tok.set_tokenid(TokenId::StringId(self.string()?)); },
+			3 => {
+				let n = self.u32()? as usize;
+				if n < 3 { return Err(invalid("Invalid polygon in index file")); }
+				let points = (0..n).map( |_| Ok((self.u32()?, self.u32()?)) ).collect::<io::Result<Vec<(u32, u32)>>>()?;
+				tok.set_tokenid(TokenId::Polygon(Polygon::new(points)));
+			},
+			4 => { tok.set_tokenid(TokenId::Line(self.string()?)); },
+			5 => {
+				let (line, start, end) = (self.string()?, self.u32()?, self.u32()?);
+				if start > end { return Err(invalid("Invalid span in index file")); }
+				tok.set_tokenid(TokenId::Span(line, start, end));
+			},
+			_ => return Err(invalid("Unknown token id type in index file")),
+		}
+		let flags = self.u8()?;
+		if flags & HAS_DOCUMENT != 0 { tok.document = Some(self.string()?); }
+		if flags & HAS_TRANSCRIPTION != 0 { tok.transcription = Some(self.string()?); }
+		if flags & HAS_RELEVANCE != 0 { tok.relevance = Some(self.f32()?); }
+		if flags & HAS_SCORE != 0 { tok.score = Some(self.f32()?); }
+		if flags & HAS_GRADE != 0 { tok.grade = Some(self.u32()? as i32); }
+		Ok(tok)
+	}
+}
+
+/// Writes the relevant tokens and query instances of `data`, parsed from `source`, to the index file `out`.
+/// Fails if `source` is not a file.
+pub fn write_index(data: &RetrievalData, source: &str, out: &str) -> io::Result<()> {
+	let relevant_tokens = match data.relevant_tokens {
+		Some(ref r) => r,
+		None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No relevance file has been loaded")),
+	};
+	let mut payload = Vec::new();
+	let mut instances: Vec<(&String, &RelevantToken)> = data.query_instances.iter().collect();
+	instances.sort_by( |a, b| a.0.cmp(b.0) );
+	put_u32(&mut payload, instances.len() as u32);
+	for (queryname, tok) in instances {
+		put_str(&mut payload, queryname);
+		put_token(&mut payload, tok);
+	}
+	let mut queries: Vec<(&String, &Vec<RelevantToken>)> = relevant_tokens.iter().collect();
+	queries.sort_by( |a, b| a.0.cmp(b.0) );
+	put_u32(&mut payload, queries.len() as u32);
+	for (queryname, tokens) in queries {
+		let mut record = Vec::new();
+		put_u32(&mut record, tokens.len() as u32);
+		for tok in tokens { put_token(&mut record, tok); }
+		for (key, i) in match_keys(tokens) {
+			put_u64(&mut record, key);
+			put_u32(&mut record, i as u32);
+		}
+		put_str(&mut payload, queryname);
+		put_u64(&mut payload, record.len() as u64);
+		payload.extend_from_slice(&record);
+	}
+	let mut header = Vec::new();
+	header.extend_from_slice(INDEX_MAGIC);
+	put_u32(&mut header, INDEX_VERSION);
+	put_u64(&mut header, file_checksum(source)?);
+	let source = fs::canonicalize(source)?;
+	put_str(&mut header, &source.to_string_lossy());
+	put_u64(&mut header, fnv1a(FNV_OFFSET, &payload));
+	let mut f = File::create(out)?;
+	f.write_all(&header)?;
+	f.write_all(&payload)
+}
+
+/// Loads the relevant tokens and query instances of an index file into `data`.
+/// Fails if the file is corrupt, has an unsupported version, or if its source file has changed
+/// since the index was written; or if the source file no longer exists, unless `allow_missing_source`
+/// is set. Queries that have not been selected are skipped.
+pub fn read_index(data: &mut RetrievalData, filename: &str, allow_missing_source: bool) -> io::Result<()> {
+	let mut bytes = Vec::new();
+	File::open(filename)?.read_to_end(&mut bytes)?;
+	let mut c = Cursor { bytes: &bytes, pos: 0 };
+	if c.take(INDEX_MAGIC.len())? != INDEX_MAGIC { return Err(invalid("Not an index file")); }
+	let version = c.u32()?;
+	if version != INDEX_VERSION {
+		return Err(invalid(&format!("Unsupported index version {} (expected {}); please rebuild the index", version, INDEX_VERSION)));
+	}
+	let source_checksum = c.u64()?;
+	let source = c.string()?;
+	match file_checksum(&source) {
+		Ok(sum) if sum != source_checksum =>
+			return Err(invalid(&format!("{} has changed since the index was built; please rebuild the index", source))),
+		Ok(_) => {},
+		Err(_) if allow_missing_source =>
+			eprintln!("Warning: source file {} of index {} not found; skipping checksum validation", source, filename),
+		Err(e) => return Err(io::Error::new(e.kind(),
+			format!("Source file {} of index {} could not be read ({}); it is needed to check that the index is up to date", source, filename, e))),
+	}
+	let payload_checksum = c.u64()?;
+	if fnv1a(FNV_OFFSET, &bytes[c.pos..]) != payload_checksum { return Err(invalid("Index file checksum mismatch")); }
+
+	for _ in 0..c.u32()? {
+		let queryname = c.string()?;
+		let tok = c.token()?;
+		data.query_instances.insert(queryname, tok);
+	}
+	let mut res = HashMap::new();
+	data.match_keys.clear();
+	for _ in 0..c.u32()? {
+		let queryname = c.string()?;
+		let len = c.u64()? as usize;
+		if !data.is_selected(&queryname) {
+			c.take(len)?;
+			continue;
+		}
+		let n = c.u32()?;
+		let mut tokens = Vec::with_capacity(n as usize);
+		for _ in 0..n { tokens.push(c.token()?); }
+		let mut keys = Vec::with_capacity(n as usize);
+		for _ in 0..n {
+			let (key, i) = (c.u64()?, c.u32()? as usize);
+			if i >= tokens.len() || keys.last().is_some_and( |&(k, _)| k > key ) { return Err(invalid("Invalid match keys in index file")); }
+			keys.push((key, i));
+		}
+		data.match_keys.insert(queryname.clone(), keys);
+		res.insert(queryname, tokens);
+	}
+	data.relevant_tokens = Some(res);
+	Ok(())
+}
+
+#[test]
+fn test_index_roundtrip() {
+	use std::env;
+	use std::fs;
+	use parsers::{FileType, ParserXmlICFHR14};
+	use metrics::Benchmark;
+
+	let dir = env::temp_dir().join(format!("rusteval-index-test-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let source = dir.join("gt.xml");
+	fs::copy("fixtures/GroundTruthRelevanceJudgementsQbESample.xml", &source).unwrap();
+	let source = source.to_str().unwrap().to_string();
+	let index = dir.join("gt.rvi").to_str().unwrap().to_string();
+
+	let mut parsed = RetrievalData::new();
+	parsed.set_relevants_filename(source.clone());
+	parsed.parse_file(FileType::RelevantTokensFile);
+	write_index(&parsed, &source, &index).unwrap();
+	assert!(is_index_file(&index) && !is_index_file(&source));
+
+	// The index is loaded transparently in place of the relevance file
+	let mut loaded = RetrievalData::new();
+	loaded.set_relevants_filename(index.clone());
+	loaded.parse_file(FileType::RelevantTokensFile);
+	assert!(loaded.query_instances.contains_key("sb0000"));
+	for q in ["sb0000", "sb0001"].iter() {
+		assert_eq!(loaded.num_relevants(q), parsed.num_relevants(q));
+		assert_eq!(loaded.match_keys[*q], match_keys(&loaded.relevant_tokens().unwrap()[*q]));
+	}
+	for data in [&mut parsed, &mut loaded].iter_mut() {
+		data.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+		data.parse_file(FileType::ResultsFile);
+	}
+	for b in parsed.computed_benchmarks() {
+		assert_eq!(loaded.compute_average_benchmark(&b).scalar(), parsed.compute_average_benchmark(&b).scalar());
+	}
+
+	// Unselected queries are skipped
+	let mut selected = RetrievalData::new();
+	selected.set_selected_queries(["sb0001"].iter().map( |q| String::from(*q) ).collect());
+	read_index(&mut selected, &index, false).unwrap();
+	assert_eq!(selected.relevant_tokens().unwrap().len(), 1);
+
+	// A corrupt index is rejected
+	let mut bytes = fs::read(&index).unwrap();
+	let last = bytes.len() - 1;
+	bytes[last] ^= 0xff;
+	let corrupt = dir.join("corrupt.rvi").to_str().unwrap().to_string();
+	fs::write(&corrupt, &bytes).unwrap();
+	assert!(read_index(&mut RetrievalData::new(), &corrupt, false).is_err());
+
+	// The source path is stored canonicalized, so the index does not depend on the working directory
+	let relative = "fixtures/../fixtures/GroundTruthRelevanceJudgementsQbESample.xml";
+	let moved = dir.join("relative.rvi").to_str().unwrap().to_string();
+	write_index(&parsed, relative, &moved).unwrap();
+	let canonical = fs::canonicalize(relative).unwrap();
+	let canonical = canonical.to_str().unwrap().as_bytes();
+	assert!(fs::read(&moved).unwrap().windows(canonical.len()).any( |w| w == canonical ));
+
+	// A missing source file is an error, unless allowed
+	let kept = dir.join("kept.xml");
+	fs::rename(&source, &kept).unwrap();
+	assert!(read_index(&mut RetrievalData::new(), &index, false).is_err());
+	assert!(read_index(&mut RetrievalData::new(), &index, true).is_ok());
+	fs::rename(&kept, &source).unwrap();
+
+	// A changed source file makes the index stale
+	fs::OpenOptions::new().append(true).open(&source).unwrap().write_all(b"\n").unwrap();
+	assert!(read_index(&mut RetrievalData::new(), &index, false).is_err());
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,362 @@
+//! C interface to the evaluator, for calling rusteval from other languages.
+//!
+//! A `RustevalData` is created with `rusteval_new`, filled with relevant and result tokens,
+//! evaluated with `rusteval_evaluate` and released with `rusteval_free`.
+//! Strings are NUL-terminated UTF-8. No function panics across the interface;
+//! failures are reported through the returned `RustevalStatus`.
+//! The header `include/rusteval.h` is generated from this file with cbindgen (see README).
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use rectangles::Rectangle;
+use retrieval::RetrievalData;
+use tokens::{RelevantToken, TokenId};
+use metrics::{Benchmark, BenchmarkResult};
+
+/// Opaque evaluation object: relevance judgements, results and the metrics computed on them.
+pub struct RustevalData(RetrievalData);
+
+/// Outcome of a call to the C interface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RustevalStatus {
+	Ok = 0,
+	/// A required pointer argument was NULL.
+	NullPointer = 1,
+	/// A string argument was not valid UTF-8.
+	InvalidUtf8 = 2,
+	/// A numerical argument was out of range (e.g. a relevance outside (0, 1] or an empty box).
+	InvalidArgument = 3,
+	/// The query has no relevant tokens, or has not been evaluated.
+	UnknownQuery = 4,
+	/// The metric name is not one of the computed benchmarks.
+	UnknownMetric = 5,
+	/// There is no value to return, e.g. a mean was requested before any evaluation.
+	NoValue = 6,
+	/// An internal error occurred; the data object should not be used any further.
+	Panic = 7,
+}
+
+/// Runs `f`, turning a panic into `RustevalStatus::Panic`.
+fn guard<F: FnOnce() -> RustevalStatus>(f: F) -> RustevalStatus {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(RustevalStatus::Panic)
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, RustevalStatus> {
+	if s.is_null() { return Err(RustevalStatus::NullPointer); }
+	CStr::from_ptr(s).to_str().map_err( |_| RustevalStatus::InvalidUtf8 )
+}
+
+unsafe fn to_data<'a>(data: *mut RustevalData) -> Result<&'a mut RetrievalData, RustevalStatus> {
+	data.as_mut().map( |d| &mut d.0 ).ok_or(RustevalStatus::NullPointer)
+}
+
+/// Builds a token; `document` may be NULL.
+unsafe fn make_token(document: *const c_char, id: TokenId) -> Result<RelevantToken, RustevalStatus> {
+	let mut tok = RelevantToken::new();
+	tok.set_tokenid(id);
+	if !document.is_null() {
+		tok.set_document(String::from(to_str(document)?));
+	}
+	Ok(tok)
+}
+
+fn make_box(x: u32, y: u32, w: u32, h: u32) -> Result<TokenId, RustevalStatus> {
+	Rectangle::from_min_size(x, y, w, h)
+		.map(TokenId::BoundingBox)
+		.map_err( |_| RustevalStatus::InvalidArgument )
+}
+
+/// How relevant a relevant token is: a relevance in (0, 1], or an integer grade.
+enum Judgement {
+	Relevance(f32),
+	Grade(i32),
+}
+
+unsafe fn add_relevant(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	id: Result<TokenId, RustevalStatus>, judgement: Judgement) -> Result<(), RustevalStatus> {
+	let data = to_data(data)?;
+	let query = to_str(query)?;
+	let mut tok = make_token(document, id?)?;
+	match judgement {
+		Judgement::Relevance(r) if r > 0.0 && r <= 1.0 => { tok.set_relevance(r); },
+		Judgement::Relevance(_) => return Err(RustevalStatus::InvalidArgument),
+		Judgement::Grade(g) => { tok.set_grade(g); },
+	}
+	data.add_relevant_token(query, tok);
+	Ok(())
+}
+
+unsafe fn add_result(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	id: Result<TokenId, RustevalStatus>, score: f32) -> Result<(), RustevalStatus> {
+	let data = to_data(data)?;
+	let query = to_str(query)?;
+	let mut tok = make_token(document, id?)?;
+	if !score.is_nan() { tok.set_score(score); }
+	data.add_result_token(query, tok);
+	Ok(())
+}
+
+fn status(r: Result<(), RustevalStatus>) -> RustevalStatus {
+	r.err().unwrap_or(RustevalStatus::Ok)
+}
+
+fn store_value(res: &BenchmarkResult, value: &mut f32) -> RustevalStatus {
+	match res.scalar() {
+		Some(v) => { *value = v; RustevalStatus::Ok },
+		None => RustevalStatus::NoValue,
+	}
+}
+
+/// Creates an empty evaluation object, to be released with `rusteval_free`.
+#[no_mangle]
+pub extern "C" fn rusteval_new() -> *mut RustevalData {
+	panic::catch_unwind( || Box::into_raw(Box::new(RustevalData(RetrievalData::new()))) ).unwrap_or(ptr::null_mut())
+}
+
+/// Releases an evaluation object. Passing NULL is a no-op.
+///
+/// # Safety
+/// `data` must be NULL or a pointer returned by `rusteval_new` that has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_free(data: *mut RustevalData) {
+	if !data.is_null() {
+		drop(Box::from_raw(data));
+	}
+}
+
+/// Only the first `depth` retrieved tokens of each query are taken into account.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_set_depth(data: *mut RustevalData, depth: usize) -> RustevalStatus {
+	guard( || status(to_data(data).map( |d| d.set_depth(depth) )) )
+}
+
+/// Relevant tokens with relevance below `min_relevance` are treated as non-relevant.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_set_min_relevance(data: *mut RustevalData, min_relevance: f32) -> RustevalStatus {
+	if min_relevance.is_nan() { return RustevalStatus::InvalidArgument; }
+	guard( || status(to_data(data).map( |d| d.set_min_relevance(min_relevance) )) )
+}
+
+/// Maps the relevance grade `grade` to `gain` in graded metrics (nDCG); unmapped grades are their own gain.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_set_gain(data: *mut RustevalData, grade: i32, gain: f32) -> RustevalStatus {
+	if !gain.is_finite() { return RustevalStatus::InvalidArgument; }
+	guard( || status(to_data(data).map( |d| { d.gains.get_or_insert_with(HashMap::new).insert(grade, gain); } )) )
+}
+
+/// Adds a relevant bounding box of `w` x `h` pixels with top-left corner (`x`, `y`).
+/// `document` may be NULL; `relevance` must lie in (0, 1], use 1.0 for binary relevance.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_relevant_box(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	x: u32, y: u32, w: u32, h: u32, relevance: f32) -> RustevalStatus {
+	guard( || status(add_relevant(data, query, document, make_box(x, y, w, h), Judgement::Relevance(relevance))) )
+}
+
+/// Adds a relevant bounding box with an integer relevance `grade`, e.g. 0 to 3 as in graded trec qrels.
+/// Tokens with a grade of 0 or less are judged non-relevant, unless a lower minimum relevance is set.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_graded_box(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	x: u32, y: u32, w: u32, h: u32, grade: i32) -> RustevalStatus {
+	guard( || status(add_relevant(data, query, document, make_box(x, y, w, h), Judgement::Grade(grade))) )
+}
+
+/// Adds a relevant token identified by the string `id`.
+/// `document` may be NULL; `relevance` must lie in (0, 1], use 1.0 for binary relevance.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_relevant_string(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	id: *const c_char, relevance: f32) -> RustevalStatus {
+	guard( || status(add_relevant(data, query, document, to_str(id).map( |s| TokenId::StringId(String::from(s)) ), Judgement::Relevance(relevance))) )
+}
+
+/// Adds a relevant token identified by the string `id`, with an integer relevance `grade`.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_graded_string(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	id: *const c_char, grade: i32) -> RustevalStatus {
+	guard( || status(add_relevant(data, query, document, to_str(id).map( |s| TokenId::StringId(String::from(s)) ), Judgement::Grade(grade))) )
+}
+
+/// Appends a retrieved bounding box to the result list of `query`.
+/// Results must be added from best to worst match; a NaN `score` means no score.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_result_box(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	x: u32, y: u32, w: u32, h: u32, score: f32) -> RustevalStatus {
+	guard( || status(add_result(data, query, document, make_box(x, y, w, h), score)) )
+}
+
+/// Appends a retrieved token identified by the string `id` to the result list of `query`.
+/// Results must be added from best to worst match; a NaN `score` means no score.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`; strings must be NULL or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_add_result_string(data: *mut RustevalData, query: *const c_char, document: *const c_char,
+	id: *const c_char, score: f32) -> RustevalStatus {
+	guard( || status(add_result(data, query, document, to_str(id).map( |s| TokenId::StringId(String::from(s)) ), score)) )
+}
+
+/// Computes all metrics for every query with a result list.
+/// Fails with `UnknownQuery` if a result list has no relevant tokens.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_evaluate(data: *mut RustevalData) -> RustevalStatus {
+	guard( || {
+		let data = match to_data(data) { Ok(d) => d, Err(e) => return e };
+		if let Some(results) = data.result_tokens() {
+			let relevants = data.relevant_tokens();
+			if results.keys().any( |q| !relevants.is_some_and( |r| r.contains_key(q) ) ) {
+				return RustevalStatus::UnknownQuery;
+			}
+		}
+		data.evaluate();
+		RustevalStatus::Ok
+	})
+}
+
+/// Stores in `value` the metric `metric` (e.g. "ap", "precAt5", "tieAp") of query `query`.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`, strings NUL-terminated
+/// and `value` a valid pointer to a float.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_query_metric(data: *const RustevalData, query: *const c_char, metric: *const c_char,
+	value: *mut f32) -> RustevalStatus {
+	guard( || {
+		let r = (|| {
+			let data = data.as_ref().map( |d| &d.0 ).ok_or(RustevalStatus::NullPointer)?;
+			let (query, metric) = (to_str(query)?, to_str(metric)?);
+			let value = value.as_mut().ok_or(RustevalStatus::NullPointer)?;
+			let res = data.benchmark_results().get(query).ok_or(RustevalStatus::UnknownQuery)?
+				.get(metric).ok_or(RustevalStatus::UnknownMetric)?;
+			Ok(store_value(res, value))
+		})();
+		r.unwrap_or_else( |e| e )
+	})
+}
+
+/// Stores in `value` the mean of metric `metric` over all evaluated queries.
+///
+/// # Safety
+/// `data` must be a live pointer returned by `rusteval_new`, `metric` NUL-terminated
+/// and `value` a valid pointer to a float.
+#[no_mangle]
+pub unsafe extern "C" fn rusteval_mean_metric(data: *const RustevalData, metric: *const c_char,
+	value: *mut f32) -> RustevalStatus {
+	guard( || {
+		let r = (|| {
+			let data = data.as_ref().map( |d| &d.0 ).ok_or(RustevalStatus::NullPointer)?;
+			let metric = to_str(metric)?;
+			let value = value.as_mut().ok_or(RustevalStatus::NullPointer)?;
+			if data.benchmark_results().is_empty() { return Err(RustevalStatus::NoValue); }
+			if !data.computed_benchmarks().iter().any( |b| b == metric ) { return Err(RustevalStatus::UnknownMetric); }
+			Ok(store_value(&data.compute_average_benchmark(metric), value))
+		})();
+		r.unwrap_or_else( |e| e )
+	})
+}
+
+/// A static, NUL-terminated description of `status`.
+#[no_mangle]
+pub extern "C" fn rusteval_status_message(status: RustevalStatus) -> *const c_char {
+	let msg: &'static [u8] = match status {
+		RustevalStatus::Ok => b"ok\0",
+		RustevalStatus::NullPointer => b"a required pointer argument was NULL\0",
+		RustevalStatus::InvalidUtf8 => b"a string argument was not valid UTF-8\0",
+		RustevalStatus::InvalidArgument => b"a numerical argument was out of range\0",
+		RustevalStatus::UnknownQuery => b"unknown query\0",
+		RustevalStatus::UnknownMetric => b"unknown metric\0",
+		RustevalStatus::NoValue => b"no value has been computed\0",
+		RustevalStatus::Panic => b"internal error\0",
+	};
+	msg.as_ptr() as *const c_char
+}
+
+#[test]
+fn test_c_interface() {
+	use std::ffi::CString;
+	let q = CString::new("q").unwrap();
+	let doc = CString::new("page1").unwrap();
+	let ap = CString::new("ap").unwrap();
+	let ids: Vec<CString> = ["a", "b", "c", "d"].iter().map( |s| CString::new(*s).unwrap() ).collect();
+	unsafe {
+		let data = rusteval_new();
+		assert_eq!(rusteval_add_relevant_box(data, q.as_ptr(), doc.as_ptr(), 10, 10, 5, 5, 1.0), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_relevant_string(data, q.as_ptr(), ptr::null(), ids[0].as_ptr(), 1.0), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_relevant_string(data, q.as_ptr(), ptr::null(), ids[1].as_ptr(), 1.5), RustevalStatus::InvalidArgument);
+		assert_eq!(rusteval_add_relevant_box(data, q.as_ptr(), doc.as_ptr(), 10, 10, 0, 5, 1.0), RustevalStatus::InvalidArgument);
+		assert_eq!(rusteval_add_result_string(data, q.as_ptr(), ptr::null(), ids[2].as_ptr(), 0.9), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_result_box(data, q.as_ptr(), doc.as_ptr(), 10, 10, 5, 5, 0.8), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_result_string(data, q.as_ptr(), ptr::null(), ids[0].as_ptr(), 0.7), RustevalStatus::Ok);
+		let mut value = 0.0;
+		assert_eq!(rusteval_mean_metric(data, ap.as_ptr(), &mut value), RustevalStatus::NoValue);
+		assert_eq!(rusteval_evaluate(data), RustevalStatus::Ok);
+		assert_eq!(rusteval_query_metric(data, q.as_ptr(), ap.as_ptr(), &mut value), RustevalStatus::Ok);
+		assert!((value - (1.0 / 2.0 + 2.0 / 3.0) / 2.0).abs() < 0.0001);
+		assert_eq!(rusteval_mean_metric(data, ap.as_ptr(), &mut value), RustevalStatus::Ok);
+		assert_eq!(rusteval_query_metric(data, q.as_ptr(), doc.as_ptr(), &mut value), RustevalStatus::UnknownMetric);
+		assert_eq!(rusteval_query_metric(data, doc.as_ptr(), ap.as_ptr(), &mut value), RustevalStatus::UnknownQuery);
+		assert_eq!(rusteval_query_metric(data, ptr::null(), ap.as_ptr(), &mut value), RustevalStatus::NullPointer);
+		// A result list without relevant tokens is reported, not a panic
+		assert_eq!(rusteval_add_result_string(data, doc.as_ptr(), ptr::null(), ids[3].as_ptr(), 0.5), RustevalStatus::Ok);
+		assert_eq!(rusteval_evaluate(data), RustevalStatus::UnknownQuery);
+		rusteval_free(data);
+
+		// Graded relevance: a hit of grade 3 at rank 2, out of grades 3 and 1, with gains 7 and 1
+		let ndcg = CString::new("ndcg").unwrap();
+		let data = rusteval_new();
+		assert_eq!(rusteval_add_graded_string(data, q.as_ptr(), ptr::null(), ids[0].as_ptr(), 3), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_graded_string(data, q.as_ptr(), ptr::null(), ids[1].as_ptr(), 1), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_graded_box(data, q.as_ptr(), doc.as_ptr(), 10, 10, 5, 5, 0), RustevalStatus::Ok);
+		assert_eq!(rusteval_set_gain(data, 3, 7.0), RustevalStatus::Ok);
+		assert_eq!(rusteval_set_gain(data, 1, f32::NAN), RustevalStatus::InvalidArgument);
+		assert_eq!(rusteval_add_result_box(data, q.as_ptr(), doc.as_ptr(), 10, 10, 5, 5, 0.9), RustevalStatus::Ok);
+		assert_eq!(rusteval_add_result_string(data, q.as_ptr(), ptr::null(), ids[0].as_ptr(), 0.8), RustevalStatus::Ok);
+		assert_eq!(rusteval_evaluate(data), RustevalStatus::Ok);
+		assert_eq!(rusteval_query_metric(data, q.as_ptr(), ndcg.as_ptr(), &mut value), RustevalStatus::Ok);
+		assert!((value - (7.0 / 3f32.log2()) / (7.0 + 1.0 / 3f32.log2())).abs() < 0.0001);
+		rusteval_free(data);
+	}
+}
+
+/// The checked-in header must match the interface; skipped where cbindgen is not installed.
+#[test]
+fn test_c_header_up_to_date() {
+	use std::fs;
+	use std::process::Command;
+	let output = match Command::new("cbindgen").args(["--config", "cbindgen.toml", "--quiet"]).output() {
+		Ok(output) => output,
+		Err(_) => { eprintln!("cbindgen not found; the C header was not checked"); return; },
+	};
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	assert!(String::from_utf8(output.stdout).unwrap() == fs::read_to_string("include/rusteval.h").unwrap(),
+		"include/rusteval.h is out of date; regenerate it with cbindgen --config cbindgen.toml --output include/rusteval.h");
+}
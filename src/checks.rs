@@ -0,0 +1,322 @@
+//! Sanity checks of a submitted result file against the ground truth (`rusteval check-run`).
+//!
+//! The result file is read line by line without building tokens, so that malformed
+//! entries (e.g. negative box sizes) are reported rather than aborting the parser.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io;
+use std::io::BufRead;
+use regex::Regex;
+
+use input::open_input;
+use parsers::{FileFormat, parse_points, parse_line, xml_unescape};
+use tokens::{RelevantToken, TokenId};
+use writers::encode_docno;
+use retrieval::RetrievalData;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+	Warning,
+	/// Hard errors make a submission invalid.
+	Error,
+}
+
+#[derive(Debug)]
+pub struct Issue {
+	pub severity: Severity,
+	/// None for issues concerning the whole file.
+	pub query: Option<String>,
+	pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct RunReport {
+	pub issues: Vec<Issue>,
+}
+
+impl RunReport {
+	fn add(&mut self, severity: Severity, query: Option<&str>, message: String) {
+		self.issues.push(Issue { severity, query: query.map(String::from), message });
+	}
+	pub fn num_errors(&self) -> usize { self.issues.iter().filter( |i| i.severity == Severity::Error ).count() }
+	pub fn num_warnings(&self) -> usize { self.issues.iter().filter( |i| i.severity == Severity::Warning ).count() }
+	pub fn print(&self) {
+		for issue in &self.issues {
+			let severity = match issue.severity {
+				Severity::Warning => "WARNING",
+				Severity::Error => "ERROR",
+			};
+			println!("{}\t{}\t{}", issue.query.as_ref().map_or("-", |q| q.as_str()), severity, issue.message);
+		}
+		println!("{} errors, {} warnings", self.num_errors(), self.num_warnings());
+	}
+}
+
+/// A retrieved token as written in the result file.
+struct RunEntry {
+	line: usize,
+	/// Identifies the token, as a trec docno.
+	key: String,
+	document: Option<String>,
+	/// The box as (x, y, width, height), if the token is a bounding box.
+	coords: Option<(i64, i64, i64, i64)>,
+	score: Option<f64>,
+}
+
+/// The result lists of a run, in file order.
+type RunLists = Vec<(String, Vec<RunEntry>)>;
+
+fn read_icfhr14_run(filename: &str, report: &mut RunReport) -> io::Result<RunLists> {
+	let re_querystarts = Regex::new(r#"<Rel queryid="([^"]*)""#).unwrap();
+	let re_attribute = Regex::new(r#"([A-Za-z]+)="([^"]*)""#).unwrap();
+	let mut lists: RunLists = Vec::new();
+	let mut in_block = false;
+	for (n, buffer) in open_input(filename)?.lines().enumerate() {
+		let current_line = buffer?;
+		if let Some(captured) = re_querystarts.captures_iter(&current_line).next() {
+			lists.push((String::from(captured.at(1).unwrap()), Vec::new()));
+			in_block = true;
+		} else if current_line.contains("</Rel>") {
+			in_block = false;
+		} else if in_block && current_line.contains("<word") {
+			let query = lists.last().unwrap().0.clone();
+			let attributes: HashMap<&str, &str> = re_attribute.captures_iter(&current_line)
+				.map( |c| (c.at(1).unwrap(), c.at(2).unwrap()) )
+				.collect();
+			let document = attributes.get("document").map( |d| xml_unescape(d) );
+			let coords: Vec<Option<i64>> = ["x", "y", "width", "height"].iter()
+				.map( |a| attributes.get(a).and_then( |v| v.parse().ok() ) )
+				.collect();
+			let score = attributes.get("Score").map( |s| s.parse::<f64>() );
+			// Lines, or spans if a start and an end are given, which must then be valid offsets
+			let line_id = attributes.get("line").and_then( |l| match (attributes.get("start"), attributes.get("end")) {
+				(None, None) => Some(TokenId::Line(String::from(*l))),
+				(Some(s), Some(e)) => Some(parse_line(l, Some((s, e)))).filter( |id| matches!(id, TokenId::Span(..)) ),
+				_ => None,
+			});
+			match (document, &coords[..], score) {
+				(Some(d), &[Some(x), Some(y), Some(w), Some(h)], score) if score.as_ref().is_none_or( |s| s.is_ok() ) => {
+					lists.last_mut().unwrap().1.push(RunEntry {
+						line: n + 1,
+						key: format!("{}_{}_{}_{}_{}", d, x, y, w, h),
+						document: Some(d.clone()),
+						coords: Some((x, y, w, h)),
+						score: score.map( |s| s.unwrap() ),
+					});
+				},
+				(Some(d), _, score) if score.as_ref().is_none_or( |s| s.is_ok() ) && attributes.get("points").and_then( |p| parse_points(p) ).is_some() => {
+					let mut tok = RelevantToken::new();
+					tok.set_tokenid(TokenId::Polygon(parse_points(attributes["points"]).unwrap())).set_document(d.clone());
+					lists.last_mut().unwrap().1.push(RunEntry {
+						line: n + 1,
+						key: encode_docno(&tok),
+						document: Some(d.clone()),
+						coords: None,
+						score: score.map( |s| s.unwrap() ),
+					});
+				},
+				(Some(d), _, score) if score.as_ref().is_none_or( |s| s.is_ok() ) && line_id.is_some() => {
+					let mut tok = RelevantToken::new();
+					tok.set_tokenid(line_id.unwrap()).set_document(d.clone());
+					lists.last_mut().unwrap().1.push(RunEntry {
+						line: n + 1,
+						key: encode_docno(&tok),
+						document: Some(d.clone()),
+						coords: None,
+						score: score.map( |s| s.unwrap() ),
+					});
+				},
+				_ => report.add(Severity::Error, Some(&query), format!("line {}: malformed token", n + 1)),
+			}
+		}
+	}
+	Ok(lists)
+}
+
+/// Lines of a query are merged into one list, wherever they are in the file, as the evaluator does.
+fn read_trec_run(filename: &str, report: &mut RunReport) -> io::Result<RunLists> {
+	let mut lists: RunLists = Vec::new();
+	let mut positions: HashMap<String, usize> = HashMap::new();
+	for (n, buffer) in open_input(filename)?.lines().enumerate() {
+		let current_line = buffer?;
+		let fields: Vec<&str> = current_line.split_whitespace().collect();
+		if fields.is_empty() { continue; }
+		let k = match positions.get(fields[0]) {
+			Some(&k) => {
+				if k + 1 != lists.len() && !report.issues.iter().any( |i| i.query.as_deref() == Some(fields[0]) && i.message.contains("not grouped") ) {
+					report.add(Severity::Warning, Some(fields[0]), format!("line {}: lines of the query are not grouped, which --streaming requires", n + 1));
+				}
+				k
+			},
+			None => {
+				lists.push((String::from(fields[0]), Vec::new()));
+				positions.insert(String::from(fields[0]), lists.len() - 1);
+				lists.len() - 1
+			},
+		};
+		let score = fields.get(4).and_then( |s| s.parse::<f64>().ok() );
+		if fields.len() < 6 || score.is_none() {
+			report.add(Severity::Error, Some(fields[0]), format!("line {}: malformed line", n + 1));
+			continue;
+		}
+		// Sizes are read as signed integers, so that negative sizes are reported rather than rejected
+		let parts: Vec<&str> = fields[2].rsplitn(5, '_').collect();
+		let coords: Vec<i64> = parts.iter().take(4).filter_map( |f| f.parse().ok() ).collect();
+		let (document, coords) = if coords.len() == 4 {
+			(parts.get(4).map( |d| String::from(*d) ), Some((coords[3], coords[2], coords[1], coords[0])))
+		} else {
+			(None, None)
+		};
+		lists[k].1.push(RunEntry { line: n + 1, key: String::from(fields[2]), document, coords, score });
+	}
+	Ok(lists)
+}
+
+/// Checks the result file `run` against the relevance judgements loaded in `gt`,
+/// and against the collection of `gt`, if any.
+///
+/// Hard errors: malformed tokens, queries missing from the run, queries split over several blocks
+/// of an ICFHR'14 file, duplicated tokens, boxes of zero or negative size, documents that are not in the collection,
+/// boxes beyond their page and, in ICFHR'14 files, scores that increase down a result list.
+/// Warnings: queries that are not in the ground truth, queries whose lines are not grouped in trec files,
+/// result lists shorter than `depth`,
+/// documents not mentioned in the ground truth, and increasing similarities in trec files
+/// (which are reordered by similarity when evaluated).
+pub fn check_run(gt: &RetrievalData, run: &str, format: FileFormat, depth: Option<usize>) -> io::Result<RunReport> {
+	let relevant_tokens = match gt.relevant_tokens() {
+		Some(r) => r,
+		None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No relevance file has been loaded")),
+	};
+	let gt_documents: HashSet<&String> = relevant_tokens.values()
+		.flat_map( |tokens| tokens.iter().filter_map( |tok| tok.get_document() ) )
+		.collect();
+	let mut report = RunReport::default();
+	let lists = match format {
+		FileFormat::XmlICFHR14 => read_icfhr14_run(run, &mut report)?,
+		FileFormat::TrecEval => read_trec_run(run, &mut report)?,
+	};
+	let mut seen_queries = HashSet::new();
+	for (queryname, entries) in &lists {
+		let q = Some(queryname.as_str());
+		if !seen_queries.insert(queryname.clone()) {
+			report.add(Severity::Error, q, String::from("query appears in several blocks"));
+		}
+		if !relevant_tokens.contains_key(queryname) {
+			report.add(Severity::Warning, q, String::from("query is not in the ground truth"));
+		}
+		if let Some(d) = depth {
+			if entries.len() < d {
+				report.add(Severity::Warning, q, format!("{} results, fewer than the required depth of {}", entries.len(), d));
+			}
+		}
+		let mut first_rank: HashMap<&str, usize> = HashMap::new();
+		for (rank, entry) in entries.iter().enumerate().map( |(i, e)| (i + 1, e) ) {
+			if let Some(&r) = first_rank.get(entry.key.as_str()) {
+				report.add(Severity::Error, q, format!("line {}: token {} at rank {} duplicates rank {}", entry.line, entry.key, rank, r));
+			} else {
+				first_rank.insert(&entry.key, rank);
+			}
+			if let Some((_, _, w, h)) = entry.coords {
+				if w <= 0 || h <= 0 {
+					report.add(Severity::Error, q, format!("line {}: box {} has a zero or negative size", entry.line, entry.key));
+				}
+			}
+			if let Some(ref d) = entry.document {
+				match gt.collection().map( |c| c.pages.get(d) ) {
+					Some(None) => report.add(Severity::Error, q, format!("line {}: document {} is not in the collection", entry.line, d)),
+					Some(Some(page)) => {
+						if let Some((x, y, w, h)) = entry.coords {
+							if w > 0 && h > 0 && (x + w > page.width as i64 || y + h > page.height as i64) {
+								report.add(Severity::Error, q, format!("line {}: box {} extends beyond its page of size {}x{}",
+									entry.line, entry.key, page.width, page.height));
+							}
+						}
+					},
+					None => {},
+				}
+				if !gt_documents.contains(d) {
+					report.add(Severity::Warning, q, format!("line {}: document {} is not mentioned in the ground truth", entry.line, d));
+				}
+			}
+		}
+		for pair in entries.windows(2) {
+			if let (Some(a), Some(b)) = (pair[0].score, pair[1].score) {
+				if b > a {
+					let severity = match format {
+						FileFormat::XmlICFHR14 => Severity::Error,
+						FileFormat::TrecEval => Severity::Warning,
+					};
+					report.add(severity, q, format!("line {}: score {} is higher than the score {} before it", pair[1].line, b, a));
+				}
+			}
+		}
+	}
+	let mut missing: Vec<&String> = relevant_tokens.keys().filter( |q| !seen_queries.contains(*q) ).collect();
+	missing.sort();
+	for queryname in missing {
+		report.add(Severity::Error, Some(queryname), String::from("query is missing from the run"));
+	}
+	report.issues.sort_by( |a, b| a.query.cmp(&b.query) );
+	Ok(report)
+}
+
+#[test]
+fn test_check_run() {
+	use std::env;
+	use std::fs::File;
+	use std::io::Write;
+	use parsers::load_fixtures;
+	use writers::WriterTrecEval;
+	use parsers::FileType;
+	use collection::{BoundsPolicy, Collection};
+
+	let gt = load_fixtures(false);
+	// Pages without relevant tokens are only warned about
+	let report = check_run(&gt, "fixtures/WordSpottingResultsSample.xml", FileFormat::XmlICFHR14, None).unwrap();
+	assert_eq!(report.num_errors(), 0);
+	assert!(report.issues.iter().all( |i| i.message.contains("not mentioned in the ground truth") ));
+
+	let report = check_run(&gt, "fixtures/MalformedRunSample.xml", FileFormat::XmlICFHR14, Some(10)).unwrap();
+	let messages = |severity: Severity, query: &str| report.issues.iter()
+		.filter( |i| i.severity == severity && i.query.as_deref() == Some(query) )
+		.map( |i| i.message.clone() )
+		.collect::<Vec<String>>();
+	let errors = messages(Severity::Error, "sb0000");
+	assert_eq!(errors.len(), 5);
+	assert!(errors.iter().any( |m| m.contains("at rank 3 duplicates rank 1") ));
+	assert!(errors.iter().any( |m| m.contains("line 10: malformed") ));
+	assert_eq!(errors.iter().filter( |m| m.contains("zero or negative size") ).count(), 2);
+	assert!(errors.iter().any( |m| m.contains("score 0.7 is higher") ));
+	let warnings = messages(Severity::Warning, "sb0000");
+	assert_eq!(warnings.len(), 2);
+	assert!(warnings.iter().any( |m| m.contains("999_999_999") ));
+	assert_eq!(messages(Severity::Error, "sb0001"), vec![String::from("query is missing from the run")]);
+	assert_eq!(messages(Severity::Warning, "sb9999").len(), 2);
+	assert_eq!(report.num_errors(), 6);
+
+	// Documents missing from the collection and boxes beyond their page are errors
+	let mut paged = load_fixtures(false);
+	paged.set_collection(Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Reject));
+	let report = check_run(&paged, "fixtures/MalformedRunSample.xml", FileFormat::XmlICFHR14, None).unwrap();
+	assert!(report.issues.iter().any( |i| i.severity == Severity::Error && i.message.contains("999_999_999 is not in the collection") ));
+
+	// The same checks apply to trec files
+	let run = load_fixtures(true);
+	let path = env::temp_dir().join(format!("rusteval-check-run-{}.trec", std::process::id()));
+	let fname = path.to_str().unwrap();
+	run.write_trec(FileType::ResultsFile, &mut File::create(fname).unwrap()).unwrap();
+	let first = std::fs::read_to_string(fname).unwrap().lines().find( |l| l.starts_with("sb0000 ") ).unwrap().to_string();
+	let mut appended = File::options().append(true).open(fname).unwrap();
+	writeln!(appended, "sb0000 0 027_029_001_10_10_0_5 99 0 x").unwrap();
+	writeln!(appended, "{}", first).unwrap();
+	drop(appended);
+	let num_results = run.result_tokens().unwrap()["sb0000"].len();
+	let report = check_run(&gt, fname, FileFormat::TrecEval, Some(num_results + 3)).unwrap();
+	// Lines appended after another query are merged into the list of their query
+	assert_eq!(report.num_errors(), 2);
+	assert!(report.issues.iter().any( |i| i.severity == Severity::Warning && i.message.contains("not grouped") ));
+	assert!(report.issues.iter().any( |i| i.message.contains(&format!("{} results, fewer than the required depth of {}", num_results + 2, num_results + 3)) ));
+	assert!(report.issues.iter().any( |i| i.message.contains("zero or negative size") ));
+	assert!(report.issues.iter().any( |i| i.message.contains(&format!("at rank {} duplicates rank 1", num_results + 2)) ));
+	std::fs::remove_file(fname).unwrap();
+}
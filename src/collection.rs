@@ -0,0 +1,220 @@
+//! Collection manifests: the pages of a collection, their size and, optionally, their word segmentation.
+//!
+//! A manifest is a text file with one entry per line:
+//!
+//! ```text
+//! # document width height
+//! 027_029_001 2480 3508
+//! # document x y width height, for each word box of the segmentation (optional)
+//! 027_029_001 159 1775 184 89
+//! ```
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use input::open_input;
+use layout::read_layouts;
+use parsers::FileType;
+use polygons::Polygon;
+use rectangles::Rectangle;
+use tokens::{RelevantToken, TokenId};
+
+/// What to do with bounding boxes that extend beyond their page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoundsPolicy {
+	/// Clip boxes to the page; boxes that lie entirely outside it are removed.
+	Clip,
+	/// Remove boxes that extend beyond the page.
+	Reject,
+}
+
+#[derive(Debug, Default)]
+pub struct Page {
+	pub width: u32,
+	pub height: u32,
+	/// Word boxes of the segmentation of the page, if any.
+	pub words: HashSet<Rectangle>,
+}
+
+/// Number of tokens found invalid when checked against a collection.
+#[derive(Debug, Default, PartialEq)]
+pub struct CollectionCounts {
+	/// Tokens referring to a document that is not in the collection; these are removed.
+	pub unknown_page: usize,
+	/// Boxes extending beyond their page, clipped or removed depending on the `BoundsPolicy`.
+	pub out_of_bounds: usize,
+	/// Boxes that are not word boxes of the segmentation of their page (only counted).
+	pub not_segmented: usize,
+}
+
+#[derive(Debug)]
+pub struct Collection {
+	pub pages: HashMap<String, Page>,
+	pub policy: BoundsPolicy,
+	pub relevant: CollectionCounts,
+	pub retrieved: CollectionCounts,
+}
+
+impl Collection {
+	pub fn new(policy: BoundsPolicy) -> Collection {
+		Collection { pages: HashMap::new(), policy, relevant: CollectionCounts::default(), retrieved: CollectionCounts::default() }
+	}
+	/// Reads a collection manifest, see the module documentation for its format, or the pages
+	/// of a PAGE XML, ALTO or hOCR file, or of a directory of such files (see `layout`).
+	pub fn from_file(fname: &str, policy: BoundsPolicy) -> Collection {
+		let mut collection = Collection::new(policy);
+		match read_layouts(fname).expect("Collection manifest could not be read.") {
+			Some(layout) => for p in layout {
+				let page = collection.pages.entry(p.document).or_default();
+				if let Some((w, h)) = p.size { page.width = w; page.height = h; }
+				for tok in p.words {
+					if let TokenId::BoundingBox(rect) = tok.id { page.words.insert(rect); }
+				}
+			},
+			None => collection.read_manifest(fname),
+		}
+		if let Some((d, _)) = collection.pages.iter().find( |&(_, p)| p.width == 0 || p.height == 0 ) {
+			panic!("No page size has been given for document {} in {}", d, fname);
+		}
+		collection
+	}
+	fn read_manifest(&mut self, fname: &str) {
+		let f = open_input(fname).expect("Collection manifest could not be read.");
+		for (n, buffer) in f.lines().enumerate() {
+			let current_line = buffer.unwrap();
+			let fields: Vec<&str> = current_line.split_whitespace().collect();
+			if fields.is_empty() || fields[0].starts_with('#') { continue; }
+			let values: Vec<u32> = fields[1..].iter()
+				.map( |v| v.parse().unwrap_or_else( |_| panic!("Invalid number on line {} of {}", n + 1, fname) ) )
+				.collect();
+			let page = self.pages.entry(String::from(fields[0])).or_default();
+			match values.len() {
+				2 => { page.width = values[0]; page.height = values[1]; },
+				4 => {
+					let rect = Rectangle::from_min_size(values[0], values[1], values[2], values[3])
+						.unwrap_or_else( |e| panic!("Invalid word box on line {} of {}: {}", n + 1, fname, e) );
+					page.words.insert(rect);
+				},
+				_ => panic!("Line {} of {} is neither a page size nor a word box", n + 1, fname),
+			}
+		}
+	}
+	/// The pixels of the page.
+	fn bounds(page: &Page) -> Rectangle {
+		Rectangle::from_min_size(0, 0, page.width, page.height).unwrap()
+	}
+	/// Checks tokens against the collection, removing tokens on unknown pages and clipping or
+	/// removing boxes and polygons beyond their page, and updates the counts of the relevant or retrieved tokens.
+	/// Polygons are clipped by moving their vertices onto the page border. Tokens without a document are left untouched.
+	pub fn validate(&mut self, ft: FileType, tokens: &mut Vec<RelevantToken>) {
+		let (pages, policy) = (&self.pages, self.policy);
+		let counts = match ft {
+			FileType::RelevantTokensFile => &mut self.relevant,
+			FileType::ResultsFile => &mut self.retrieved,
+		};
+		tokens.retain_mut( |tok| {
+			let page = match tok.get_document() {
+				Some(d) => match pages.get(d) {
+					Some(p) => p,
+					None => { counts.unknown_page += 1; return false; },
+				},
+				None => return true,
+			};
+			let clipped = match tok.id {
+				TokenId::BoundingBox(ref rect) if !Collection::bounds(page).contains(rect) => {
+					counts.out_of_bounds += 1;
+					if policy == BoundsPolicy::Reject { return false; }
+					match rect.intersection(&Collection::bounds(page)) {
+						Some(clipped) => Some(TokenId::BoundingBox(clipped)),
+						None => return false,
+					}
+				},
+				// Vertices lie on pixel corners, so a polygon may reach the far border of its page
+				TokenId::Polygon(ref poly) if poly.points().iter().any( |&(x, y)| x > page.width || y > page.height ) => {
+					counts.out_of_bounds += 1;
+					if policy == BoundsPolicy::Reject { return false; }
+					let bb = poly.bounding_box();
+					if bb.min().get_x() >= page.width || bb.min().get_y() >= page.height { return false; }
+					Some(TokenId::Polygon(Polygon::new(poly.points().iter()
+						.map( |&(x, y)| (x.min(page.width), y.min(page.height)) ).collect())))
+				},
+				_ => None,
+			};
+			if let Some(id) = clipped { tok.set_tokenid(id); }
+			if let TokenId::BoundingBox(ref rect) = tok.id {
+				if !page.words.is_empty() && !page.words.contains(rect) { counts.not_segmented += 1; }
+			}
+			true
+		});
+	}
+	pub fn print(&self) {
+		println!();
+		println!("COLLECTION\t{:16}{:16}", "relevant", "retrieved");
+		println!("=======================================================================");
+		println!("unknownPage\t{:<16}{:<16}", self.relevant.unknown_page, self.retrieved.unknown_page);
+		println!("outOfBounds\t{:<16}{:<16}", self.relevant.out_of_bounds, self.retrieved.out_of_bounds);
+		println!("notSegmented\t{:<16}{:<16}", self.relevant.not_segmented, self.retrieved.not_segmented);
+	}
+}
+
+#[test]
+fn test_collection_validation() {
+	let token = |document: &str, x: u32, y: u32, w: u32, h: u32| {
+		let rect = Rectangle::from_min_size(x, y, w, h).unwrap();
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::BoundingBox(rect)).set_document(String::from(document));
+		tok
+	};
+	let tokens = || vec![
+		token("027_029_001", 159, 1775, 184, 89),
+		token("027_029_001", 2400, 100, 200, 50),	// crosses the right border
+		token("027_029_001", 2500, 100, 200, 50),	// entirely outside
+		token("999_999_999", 10, 10, 10, 10),
+		token("071_053_004", 92, 607, 220, 138),
+	];
+	let mut collection = Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Clip);
+	assert_eq!(collection.pages.len(), 4);
+	let mut clipped = tokens();
+	collection.validate(FileType::ResultsFile, &mut clipped);
+	assert_eq!(clipped.len(), 3);
+	match clipped[1].id {
+		TokenId::BoundingBox(ref rect) => assert_eq!(*rect, Rectangle::from_min_size(2400, 100, 80, 50).unwrap()),
+		_ => panic!("Expected a bounding box"),
+	}
+	assert_eq!(collection.retrieved, CollectionCounts { unknown_page: 1, out_of_bounds: 2, not_segmented: 1 });
+	assert_eq!(collection.relevant, CollectionCounts::default());
+
+	let mut collection = Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Reject);
+	let mut rejected = tokens();
+	collection.validate(FileType::RelevantTokensFile, &mut rejected);
+	assert_eq!(rejected.len(), 2);
+	assert_eq!(collection.relevant, CollectionCounts { unknown_page: 1, out_of_bounds: 2, not_segmented: 0 });
+
+	// Polygons and lines are checked as well; 027_029_001 is 2480 pixels wide
+	let other = |document: &str, id: TokenId| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(id).set_document(String::from(document));
+		tok
+	};
+	let others = || vec![
+		other("027_029_001", TokenId::Polygon(Polygon::new(vec![(2400, 100), (2600, 100), (2500, 200)]))),
+		other("027_029_001", TokenId::Polygon(Polygon::new(vec![(2500, 100), (2600, 100), (2500, 200)]))),
+		other("027_029_001", TokenId::Polygon(Polygon::new(vec![(2380, 100), (2480, 100), (2480, 200)]))),
+		other("999_999_999", TokenId::Polygon(Polygon::new(vec![(10, 10), (20, 10), (20, 20)]))),
+		other("999_999_999", TokenId::Line(String::from("l01"))),
+		other("027_029_001", TokenId::Line(String::from("l01"))),
+	];
+	let mut collection = Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Clip);
+	let mut clipped = others();
+	collection.validate(FileType::ResultsFile, &mut clipped);
+	assert_eq!(clipped.len(), 3);
+	match clipped[0].id {
+		TokenId::Polygon(ref poly) => assert_eq!(poly.points(), &[(2400, 100), (2480, 100), (2480, 200)]),
+		_ => panic!("Expected a polygon"),
+	}
+	assert_eq!(collection.retrieved, CollectionCounts { unknown_page: 2, out_of_bounds: 2, not_segmented: 0 });
+	let mut collection = Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Reject);
+	let mut rejected = others();
+	collection.validate(FileType::ResultsFile, &mut rejected);
+	assert_eq!(rejected.len(), 2);
+}
@@ -0,0 +1,49 @@
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::fs::File;
+use flate2::bufread::MultiGzDecoder;
+use bzip2::bufread::MultiBzDecoder;
+
+enum Compression {
+	None,
+	Gzip,
+	Bzip2,
+	Zstd,
+}
+
+fn detect_compression(magic: &[u8]) -> Compression {
+	if magic.starts_with(&[0x1f, 0x8b]) { Compression::Gzip }
+	else if magic.starts_with(b"BZh") { Compression::Bzip2 }
+	else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) { Compression::Zstd }
+	else { Compression::None }
+}
+
+/// Opens an input file for reading line by line; "-" stands for the standard input.
+/// Gzip, bzip2 and zstd compressed input is detected by its magic bytes 
+/// and decompressed on the fly, whatever the name of the file.
+pub fn open_input(filename: &str) -> io::Result<Box<dyn BufRead>> {
+	let mut f: Box<dyn BufRead> = match filename {
+		"-" => Box::new(BufReader::new(io::stdin())),
+		_ => Box::new(BufReader::new(File::open(filename)?)),
+	};
+	// fill_buf() may return fewer bytes than the magic number only at the end of the stream
+	let compression = detect_compression(f.fill_buf()?);
+	Ok(match compression {
+		Compression::None => f,
+		Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(f))),
+		Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(f))),
+		Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(f)?)),
+	})
+}
+
+#[test]
+fn test_open_compressed_input() {
+	let plain: Vec<String> = open_input("fixtures/WordSpottingResultsSample.xml").unwrap().lines().map( |l| l.unwrap() ).collect();
+	assert!(plain.len() > 100);
+	for ext in ["gz", "bz2", "zst"].iter() {
+		let fname = format!("fixtures/WordSpottingResultsSample.xml.{}", ext);
+		let decompressed: Vec<String> = open_input(&fname).unwrap().lines().map( |l| l.unwrap() ).collect();
+		assert_eq!(plain, decompressed);
+	}
+}
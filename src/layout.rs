@@ -0,0 +1,305 @@
+//! Readers of word-level layout files: PAGE XML (e.g. Transkribus exports), ALTO and hOCR.
+//!
+//! Each page of a layout file becomes a `LayoutPage`, whose words are `RelevantToken`s with
+//! a bounding box id, the page as document and the word text as transcription. Documents are
+//! named after the page image (PAGE `imageFilename`, ALTO `fileName`, hOCR `image`), without
+//! directory and extension, or else after the layout file itself, without a `.page`, `.alto`
+//! or `.hocr` suffix before its extension.
+//!
+//! Word boxes are the bounding boxes of PAGE polygons, whose points are taken as pixels on
+//! the word, ALTO `HPOS`, `VPOS`, `WIDTH` and `HEIGHT`, and hOCR `bbox x0 y0 x1 y1`, whose
+//! right and bottom coordinates lie just outside the word, as written by Tesseract.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use regex::Regex;
+
+use input::open_input;
+use parsers::xml_unescape;
+use rectangles::Rectangle;
+use tokens::{RelevantToken, TokenId};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutFormat {
+	PageXml,
+	Alto,
+	Hocr,
+}
+
+impl LayoutFormat {
+	/// Detects the format of a layout file from its contents.
+	pub fn detect(contents: &str) -> Option<LayoutFormat> {
+		if contents.contains("<PcGts") { Some(LayoutFormat::PageXml) }
+		else if contents.contains("<alto") { Some(LayoutFormat::Alto) }
+		else if contents.contains("ocr_page") { Some(LayoutFormat::Hocr) }
+		else { None }
+	}
+}
+
+#[derive(Debug)]
+pub struct LayoutPage {
+	pub document: String,
+	/// Size of the page image in pixels, if given.
+	pub size: Option<(u32, u32)>,
+	pub words: Vec<RelevantToken>,
+}
+
+/// The attributes of a start tag; values may be quoted with either kind of quote.
+fn attributes(tag: &str) -> HashMap<String, String> {
+	let re_attribute = Regex::new(r#"([A-Za-z_][\w:.-]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+	re_attribute.captures_iter(tag)
+		.map( |c| (String::from(c.at(1).unwrap()), xml_unescape(c.at(2).or(c.at(3)).unwrap())) )
+		.collect()
+}
+
+/// The name of an image or layout file, without directory, extension and layout format suffix
+/// (e.g. `027_029_001.page.xml` and `scan.v2.jpg` are named `027_029_001` and `scan.v2`).
+fn document_name(path: &str) -> String {
+	let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+	let stem = match name.rfind('.') {
+		Some(i) if i > 0 => &name[..i],
+		_ => name,
+	};
+	let stem = [".page", ".alto", ".hocr"].iter().find_map( |suffix| stem.strip_suffix(suffix) ).unwrap_or(stem);
+	String::from(stem)
+}
+
+/// Names the pages of a file after `image`, or after the layout file; pages of multi-page files
+/// without an image name of their own are numbered.
+fn page_name(image: Option<&str>, fname: &str, index: usize, num_pages: usize) -> String {
+	match image {
+		Some(i) => document_name(i),
+		None if num_pages > 1 => format!("{}_{}", document_name(fname), index + 1),
+		None => document_name(fname),
+	}
+}
+
+fn parse_coord(v: &str) -> Option<u32> {
+	v.trim().parse::<f64>().ok().filter( |v| *v >= 0.0 ).map( |v| v.round() as u32 )
+}
+
+/// A word token; `(x0, y0, x1, y1)` is exclusive of `x1` and `y1`. Empty boxes give None.
+fn word_token(document: &str, (x0, y0, x1, y1): (u32, u32, u32, u32), text: Option<String>) -> Option<RelevantToken> {
+	if x1 <= x0 || y1 <= y0 { return None; }
+	let rect = Rectangle::from_min_max(x0, y0, x1 - 1, y1 - 1).ok()?;
+	let mut tok = RelevantToken::new();
+	tok.set_tokenid(TokenId::BoundingBox(rect)).set_document(String::from(document));
+	if let Some(t) = text.map( |t| String::from(t.trim()) ).filter( |t| !t.is_empty() ) {
+		tok.set_transcription(t);
+	}
+	Some(tok)
+}
+
+/// Splits `contents` at the start tags matched by `re_page`, returning each tag with the text up to the next one.
+fn split_pages<'a>(contents: &'a str, re_page: &Regex) -> Vec<(&'a str, &'a str)> {
+	let starts: Vec<(usize, usize)> = re_page.find_iter(contents).collect();
+	starts.iter().enumerate()
+		.map( |(i, &(s, e))| (&contents[s..e], &contents[e..starts.get(i + 1).map_or(contents.len(), |n| n.0)]) )
+		.collect()
+}
+
+fn read_page_xml(contents: &str, fname: &str) -> io::Result<Vec<LayoutPage>> {
+	let re_page = Regex::new(r"<(?:\w+:)?Page\b[^>]*>").unwrap();
+	let re_word = Regex::new(r"(?s)<(?:\w+:)?Word\b[^>]*>(.*?)</(?:\w+:)?Word>").unwrap();
+	let re_points = Regex::new(r#"<(?:\w+:)?Coords\b[^>]*points\s*=\s*"([^"]*)""#).unwrap();
+	let re_point = Regex::new(r#"<(?:\w+:)?Point\b[^>]*x\s*=\s*"([^"]*)"[^>]*y\s*=\s*"([^"]*)""#).unwrap();
+	let re_text = Regex::new(r"(?s)<(?:\w+:)?Unicode>(.*?)</(?:\w+:)?Unicode>").unwrap();
+	let pages = split_pages(contents, &re_page);
+	let mut result = Vec::new();
+	for (i, &(tag, body)) in pages.iter().enumerate() {
+		let attrs = attributes(tag);
+		let document = page_name(attrs.get("imageFilename").map( |s| s.as_str() ), fname, i, pages.len());
+		let size = match (attrs.get("imageWidth").and_then( |v| parse_coord(v) ), attrs.get("imageHeight").and_then( |v| parse_coord(v) )) {
+			(Some(w), Some(h)) => Some((w, h)),
+			_ => None,
+		};
+		let mut words = Vec::new();
+		for word in re_word.captures_iter(body) {
+			let inner = word.at(1).unwrap();
+			// PAGE 2013 and later give a points attribute, PAGE 2010 Point elements
+			let points: Vec<(u32, u32)> = match re_points.captures_iter(inner).next() {
+				Some(p) => p.at(1).unwrap().split_whitespace()
+					.filter_map( |xy| {
+						let mut c = xy.split(',').map(parse_coord);
+						match (c.next(), c.next()) { (Some(Some(x)), Some(Some(y))) => Some((x, y)), _ => None }
+					})
+					.collect(),
+				None => re_point.captures_iter(inner)
+					.filter_map( |p| match (parse_coord(p.at(1).unwrap()), parse_coord(p.at(2).unwrap())) {
+						(Some(x), Some(y)) => Some((x, y)),
+						_ => None,
+					})
+					.collect(),
+			};
+			if points.is_empty() {
+				return Err(io::Error::new(io::ErrorKind::InvalidData, format!("A word of {} has no coordinates", fname)));
+			}
+			let bounds = match (points.iter().map( |p| p.0 ).max().unwrap().checked_add(1), points.iter().map( |p| p.1 ).max().unwrap().checked_add(1)) {
+				(Some(x1), Some(y1)) => (points.iter().map( |p| p.0 ).min().unwrap(), points.iter().map( |p| p.1 ).min().unwrap(), x1, y1),
+				_ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("A word of {} is beyond the coordinate range", fname))),
+			};
+			let text = re_text.captures_iter(inner).next().map( |t| xml_unescape(t.at(1).unwrap()) );
+			words.extend(word_token(&document, bounds, text));
+		}
+		result.push(LayoutPage { document, size, words });
+	}
+	Ok(result)
+}
+
+fn read_alto(contents: &str, fname: &str) -> io::Result<Vec<LayoutPage>> {
+	let re_unit = Regex::new(r"<MeasurementUnit>\s*([^<\s]*)\s*</MeasurementUnit>").unwrap();
+	let re_image = Regex::new(r"<fileName>\s*([^<]*?)\s*</fileName>").unwrap();
+	let re_page = Regex::new(r"<Page\b[^>]*>").unwrap();
+	let re_string = Regex::new(r"<String\b[^>]*>").unwrap();
+	if let Some(unit) = re_unit.captures_iter(contents).next().and_then( |c| c.at(1) ) {
+		if unit != "pixel" {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is measured in {}; only pixel coordinates are supported", fname, unit)));
+		}
+	}
+	let image = re_image.captures_iter(contents).next().and_then( |c| c.at(1) ).map( xml_unescape );
+	let pages = split_pages(contents, &re_page);
+	let mut result = Vec::new();
+	for (i, &(tag, body)) in pages.iter().enumerate() {
+		let attrs = attributes(tag);
+		// The image file name describes the whole file, so it only names single pages
+		let document = page_name(image.as_ref().filter( |_| pages.len() == 1 ).map( |s| s.as_str() ), fname, i, pages.len());
+		let size = match (attrs.get("WIDTH").and_then( |v| parse_coord(v) ), attrs.get("HEIGHT").and_then( |v| parse_coord(v) )) {
+			(Some(w), Some(h)) => Some((w, h)),
+			_ => None,
+		};
+		let mut words = Vec::new();
+		for string in re_string.find_iter(body) {
+			let attrs = attributes(&body[string.0..string.1]);
+			let coords: Vec<Option<u32>> = ["HPOS", "VPOS", "WIDTH", "HEIGHT"].iter()
+				.map( |a| attrs.get(*a).and_then( |v| parse_coord(v) ) )
+				.collect();
+			let (x, y, w, h) = match &coords[..] {
+				&[Some(x), Some(y), Some(w), Some(h)] => (x, y, w, h),
+				_ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("A String of {} has no coordinates", fname))),
+			};
+			let (x1, y1) = match (x.checked_add(w), y.checked_add(h)) {
+				(Some(x1), Some(y1)) => (x1, y1),
+				_ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("A String of {} is beyond the coordinate range", fname))),
+			};
+			words.extend(word_token(&document, (x, y, x1, y1), attrs.get("CONTENT").cloned()));
+		}
+		result.push(LayoutPage { document, size, words });
+	}
+	Ok(result)
+}
+
+fn read_hocr(contents: &str, fname: &str) -> io::Result<Vec<LayoutPage>> {
+	let re_page = Regex::new(r#"<\w+\b[^>]*['"]ocr_page['"][^>]*>"#).unwrap();
+	let re_word = Regex::new(r#"(?s)<span\b([^>]*['"]ocrx_word['"][^>]*)>(.*?)</span>"#).unwrap();
+	let re_bbox = Regex::new(r"bbox\s+(\d+)\s+(\d+)\s+(\d+)\s+(\d+)").unwrap();
+	let re_image = Regex::new(r#"image\s+(?:"([^"]*)"|([^;\s]+))"#).unwrap();
+	let re_tag = Regex::new(r"<[^>]*>").unwrap();
+	// None if the title has no bbox, an error if its coordinates are beyond the coordinate range
+	let bbox = |title: &str| -> io::Result<Option<(u32, u32, u32, u32)>> {
+		match re_bbox.captures_iter(title).next() {
+			Some(c) => {
+				let b = (1..5).map( |i| c.at(i).unwrap().parse::<u32>() ).collect::<Result<Vec<u32>, _>>()
+					.map_err( |_| io::Error::new(io::ErrorKind::InvalidData, format!("A bbox of {} is beyond the coordinate range", fname)) )?;
+				Ok(Some((b[0], b[1], b[2], b[3])))
+			},
+			None => Ok(None),
+		}
+	};
+	let pages = split_pages(contents, &re_page);
+	let mut result = Vec::new();
+	for (i, &(tag, body)) in pages.iter().enumerate() {
+		let title = attributes(tag).remove("title").unwrap_or_default();
+		let image = re_image.captures_iter(&title).next().and_then( |c| c.at(1).or(c.at(2)) ).map(String::from);
+		let document = page_name(image.as_deref(), fname, i, pages.len());
+		let size = match bbox(&title)? {
+			Some((x0, y0, x1, y1)) => match (x1.checked_sub(x0), y1.checked_sub(y0)) {
+				(Some(w), Some(h)) => Some((w, h)),
+				_ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("A page of {} has a reversed bbox", fname))),
+			},
+			None => None,
+		};
+		let mut words = Vec::new();
+		for word in re_word.captures_iter(body) {
+			let title = attributes(word.at(1).unwrap()).remove("title").unwrap_or_default();
+			let bounds = bbox(&title)?.ok_or_else( ||
+				io::Error::new(io::ErrorKind::InvalidData, format!("A word of {} has no bbox", fname)) )?;
+			let text = xml_unescape(&re_tag.replace_all(word.at(2).unwrap(), ""));
+			words.extend(word_token(&document, bounds, Some(text)));
+		}
+		result.push(LayoutPage { document, size, words });
+	}
+	Ok(result)
+}
+
+/// Reads the pages of a PAGE XML, ALTO or hOCR file; None if the file is in neither format.
+pub fn read_layout(fname: &str) -> io::Result<Option<Vec<LayoutPage>>> {
+	let mut contents = String::new();
+	open_input(fname)?.read_to_string(&mut contents)?;
+	match LayoutFormat::detect(&contents) {
+		Some(LayoutFormat::PageXml) => read_page_xml(&contents, fname).map(Some),
+		Some(LayoutFormat::Alto) => read_alto(&contents, fname).map(Some),
+		Some(LayoutFormat::Hocr) => read_hocr(&contents, fname).map(Some),
+		None => Ok(None),
+	}
+}
+
+/// Reads a layout file, or all layout files of a directory, in the order of their names;
+/// other files of the directory are skipped. None if `path` is a file in no layout format.
+pub fn read_layouts(path: &str) -> io::Result<Option<Vec<LayoutPage>>> {
+	if !Path::new(path).is_dir() {
+		return read_layout(path);
+	}
+	let mut fnames: Vec<String> = fs::read_dir(path)?
+		.filter_map( |e| e.ok() )
+		.filter( |e| e.path().is_file() )
+		.filter_map( |e| e.path().to_str().map(String::from) )
+		.collect();
+	fnames.sort();
+	let mut pages = Vec::new();
+	for fname in fnames {
+		if let Some(p) = read_layout(&fname)? { pages.extend(p); }
+	}
+	Ok(Some(pages))
+}
+
+#[test]
+fn test_layout_readers() {
+	let boxes = |page: &LayoutPage| page.words.iter()
+		.map( |tok| match tok.id {
+			TokenId::BoundingBox(ref bb) => (bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height(),
+				tok.get_transcription().cloned().unwrap_or_default()),
+			_ => panic!("Expected a bounding box"),
+		})
+		.collect::<Vec<(u32, u32, u32, u32, String)>>();
+	let expected = vec![
+		(1460, 178, 298, 98, String::from("Jeremy")),
+		(159, 1775, 184, 89, String::from("Bentham")),
+		(860, 1774, 180, 89, String::from("&c.")),
+		(1490, 1769, 176, 86, String::new()),
+	];
+	for fname in ["fixtures/layout/027_029_001.page.xml", "fixtures/layout/027_029_001.alto.xml", "fixtures/layout/027_029_001.hocr"].iter() {
+		let pages = read_layout(fname).unwrap().unwrap();
+		assert_eq!(pages.len(), 1, "{}", fname);
+		assert_eq!(pages[0].document, "027_029_001", "{}", fname);
+		assert_eq!(pages[0].size, Some((2480, 3508)), "{}", fname);
+		assert_eq!(boxes(&pages[0]), expected, "{}", fname);
+		assert!(pages[0].words.iter().all( |tok| tok.get_document().map( |d| d.as_str() ) == Some("027_029_001") ));
+	}
+	assert_eq!(read_layouts("fixtures/layout").unwrap().unwrap().len(), 3);
+	assert!(read_layout("fixtures/AnnotationSample.txt").unwrap().is_none());
+
+	assert_eq!(document_name("images/scan.v2.jpg"), "scan.v2");
+	assert_eq!(document_name("layout/027_029_001.page.xml"), "027_029_001");
+	assert_eq!(document_name("027_029_001.hocr"), "027_029_001");
+	assert_eq!(document_name(".hidden"), ".hidden");
+	// Coordinates beyond the coordinate range and reversed page boxes are errors, not panics
+	let hocr = |page: &str, word: &str| format!(r#"<div class="ocr_page" title="{}"><span class="ocrx_word" title="{}">w</span></div>"#, page, word);
+	for contents in [hocr("bbox 0 0 2480 3508", "bbox 0 0 99999999999 10"), hocr("bbox 2480 3508 0 0", "bbox 0 0 10 10")].iter() {
+		assert_eq!(read_hocr(contents, "x.hocr").unwrap_err().kind(), io::ErrorKind::InvalidData);
+	}
+	let alto = r#"<alto><Page WIDTH="10" HEIGHT="10"><String HPOS="4294967295" VPOS="0" WIDTH="10" HEIGHT="10" CONTENT="w"/></Page></alto>"#;
+	assert_eq!(read_alto(alto, "x.alto.xml").unwrap_err().kind(), io::ErrorKind::InvalidData);
+	assert_eq!(read_hocr(&hocr("bbox 0 0 2480 3508", "bbox 1 2 11 12"), "x.hocr").unwrap()[0].words.len(), 1);
+}
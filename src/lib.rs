@@ -0,0 +1,32 @@
+//! Evaluation of the output of retrieval algorithms, such as keyword spotting systems.
+//!
+//! Relevance judgements and result lists are held in a `RetrievalData`, either parsed from
+//! files (see `parsers`) or built in memory, and the metrics of `metrics` are computed on it.
+extern crate regex;
+extern crate rand;
+extern crate flate2;
+extern crate bzip2;
+extern crate zstd;
+
+pub mod rectangles;
+pub mod polygons;
+pub mod tokens;
+pub mod input;
+pub mod retrieval;
+pub mod metrics;
+pub mod parsers;
+pub mod writers;
+pub mod capi;
+pub mod cache;
+pub mod stats;
+pub mod checks;
+pub mod collection;
+pub mod transforms;
+pub mod spatial;
+pub mod nms;
+pub mod aggregation;
+pub mod qrels;
+pub mod layout;
+
+pub use retrieval::RetrievalData;
+pub use metrics::BenchmarkResult;
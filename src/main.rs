@@ -1,599 +1,340 @@
-#![allow(dead_code)]
-mod rectangles;
-mod tokens;
-extern crate regex;
+extern crate rusteval;
 extern crate getopts;
-extern crate rand;
 
 use std::env;
-use std::io::BufReader;
-use std::io::BufRead;
-use std::fs::File;
 use std::collections::HashMap;
-use regex::Regex;
+use std::process;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
 use getopts::Options;
 
-use rectangles::Rectangle;
-use tokens::RelevantToken;
-use tokens::TokenId;
+use rusteval::RetrievalData;
+use rusteval::metrics::{Benchmark, DocumentBreakdown};
+use rusteval::cache::write_index;
+use rusteval::stats::GroundTruthStats;
+use rusteval::checks::check_run;
+use rusteval::collection::{Collection, BoundsPolicy};
+use rusteval::transforms::{Transform, Transforms, Rounding};
+use rusteval::aggregation::DocumentRanking;
+use rusteval::qrels;
+use rusteval::qrels::{Normalization, read_annotations, read_queries};
+use rusteval::parsers::{FileType, FileFormat, ParserXmlICFHR14, detect_format, parse_input, read_query_list, read_query_groups};
+use rusteval::writers::{WriterXmlICFHR14, WriterTrecEval};
 
-enum FileType {
-	ResultsFile,
-	RelevantTokensFile,
-}
-
-#[derive(Debug)]
-enum BenchmarkResult {
-	Scalar(f32),
-	Vector(Vec<f32>),
-	None,
-}
-
-struct RetrievalData {
-	relevant_tokens_filename: 	Option<String>,
-	relevant_tokens: 			Option<HashMap<String, Vec<RelevantToken>>>,
-	result_tokens_filename:		Option<String>,	
-	result_tokens: 				Option<HashMap<String, Vec<RelevantToken>>>,
-	benchmark_results:			HashMap<String, HashMap<String, BenchmarkResult>>,
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} RELEVANCE_FILE RESULT_FILE [options]\n       {} index RELEVANCE_FILE -o INDEX_FILE\n       {} convert INPUT_FILE OUTPUT_FILE --to FORMAT\n       {} stats RELEVANCE_FILE [--json]\n       {} check-run RELEVANCE_FILE RESULT_FILE [--depth N]\n       {} make-qrels ANNOTATION_FILE QUERY_FILE OUTPUT_FILE [--normalize LIST]\n\nInput files may be in the ICFHR'14 XML or in the trec_eval format, and gzip, bzip2 or zstd compressed;\n'-' reads from the standard input. An index file may be given in place of the relevance file.", program, program, program, program, program, program);
+    print!("{}", opts.usage(&brief));
 }
 
-impl RetrievalData {
-	fn new() -> RetrievalData {
-		RetrievalData { 
-			relevant_tokens_filename: None,
-			relevant_tokens: None,
-			result_tokens_filename: None,			
-			result_tokens: None,
-			benchmark_results: HashMap::new(),
-		}
+/// `rusteval index`: parses a relevance file once and stores it as a binary index file.
+fn index(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "write the index to FILE (default: RELEVANCE_FILE.rvi)", "FILE");
+    opts.optopt("", "query-instances", "also store the query instances defined in FILE", "FILE");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Ok(m) => { m }
+        Err(f) => { panic!("{}", f.to_string()) }
+    };
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&format!("Usage: {} index RELEVANCE_FILE -o INDEX_FILE", program)));
+        return;
+    }
+	let relevance_file = matches.free[0].clone();
+	let output = matches.opt_str("o").unwrap_or_else( || format!("{}.rvi", relevance_file) );
+	let mut f = RetrievalData::new();
+	if let Some(fname) = matches.opt_str("query-instances") {
+		f.set_query_instances_filename(fname);
+		f.parse_query_instances_file();
 	}
-	fn set_relevants_filename(&mut self, fname: String) { self.relevant_tokens_filename = Some(fname); }
-	fn set_results_filename(&mut self, fname: String) { self.result_tokens_filename = Some(fname); }	
-}
-
-trait MetricPrecisionAtX {
-	fn precision_at_x(&self, queryname: &String, results: &Vec<RelevantToken>, x: usize) -> BenchmarkResult;
-	fn precision_at_5(&self, queryname: &String, results: &Vec<RelevantToken>) -> BenchmarkResult {
-		self.precision_at_x(queryname, results, 5)
-	}	
-	fn precision_at_10(&self, queryname: &String, results: &Vec<RelevantToken>) -> BenchmarkResult {
-		self.precision_at_x(queryname, results, 10)
-	}		
-}
-
-trait MetricMAP {
-	fn num_recall_points(&self) -> usize { 11 }
-	fn average_precision(&self, queryname: &String, results: &Vec<RelevantToken>) -> BenchmarkResult;
-}
-
-trait Benchmark : MetricPrecisionAtX + MetricMAP {
-	fn tokens_match(&self, a: &RelevantToken, b: &RelevantToken) -> bool;	
-	fn is_this_a_hit(&self, queryname: &String, token: &RelevantToken) -> bool;
-	fn store_all_numerical_results(&mut self, queryname: &String, hitlist: &Vec<RelevantToken>);
-	fn computed_benchmarks(&self) -> Vec<String>;	
-	fn print_all_benchmarks(&self);
-	fn compute_average_benchmark(&self, benchmark: &String) -> BenchmarkResult;
+	f.set_relevants_filename(relevance_file.clone());
+	parse_input(&mut f, FileType::RelevantTokensFile, None);
+	write_index(&f, &relevance_file, &output).expect("Index file could not be written.");
 }
 
-trait ParserTrecEval : Benchmark {
+fn parse_format(name: &str) -> FileFormat {
+	FileFormat::from_name(name).unwrap_or_else( || panic!("Unknown format '{}'; expected 'icfhr' or 'trec'", name) )
 }
 
-trait ParserXmlICFHR14 : Benchmark {
-	fn parse_tokenstring_fast(&self, tokstr: String) -> RelevantToken;
-	fn parse_tokenstring(&self, tokstr: String) -> RelevantToken;
-	fn parse_file(&mut self, ft: FileType);
-}
-
-impl Benchmark for RetrievalData {
-	fn tokens_match(&self, a: &RelevantToken, b: &RelevantToken) -> bool {
-		let a_box = match a.id {
-			TokenId::BoundingBox(ref r) => r,
-			_ => panic!("Not implemented yet!"),
-		};
-		let b_box = match b.id {
-			TokenId::BoundingBox(ref r) => r,
-			_ => panic!("Not implemented yet!"),
-		};
-		//TODO: This should be done as a Rectangle operator trait
-		a_box.min.get_x() == b_box.min.get_x() && 
-		a_box.min.get_y() == b_box.min.get_y() &&
-		a_box.width() == b_box.width() &&
-		a_box.height() == b_box.height()
-	}
-	fn is_this_a_hit(&self, queryname: &String, token: &RelevantToken) -> bool {
-		match self.relevant_tokens {
-			Some(ref i) => i.get(queryname).unwrap(),
-			None => panic!(format!("Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname)),
-		}.into_iter().any( |pred| self.tokens_match(pred, token))
-	}
-	fn store_all_numerical_results(&mut self, queryname: &String, hitlist: &Vec<RelevantToken>) {
-		let mut res = HashMap::new();
-		res.insert(
-			String::from("precAt5"),
-			self.precision_at_5(queryname, hitlist),
-		);
-		res.insert(
-			String::from("precAt10"),
-			self.precision_at_10(queryname, hitlist),
-		);
-		res.insert(
-			String::from("ap"),
-			self.average_precision(queryname, hitlist),
-		);		
-		self.benchmark_results.insert(queryname.clone(), res);
+/// Parses "A,B", or "A" for "A,A" if `same` is true.
+fn parse_pair(value: &str, option: &str, same: bool) -> (f64, f64) {
+	let parse = |v: &str| v.trim().parse().unwrap_or_else( |_| panic!("--{} must be given as X,Y", option) );
+	match value.split(',').collect::<Vec<&str>>()[..] {
+		[a] if same => (parse(a), parse(a)),
+		[a, b] => (parse(a), parse(b)),
+		_ => panic!("--{} must be given as X,Y", option),
 	}
-	fn computed_benchmarks(&self) -> Vec<String> {
-		self.benchmark_results.values().nth(0).unwrap().keys().cloned().collect()
-	}
-	fn print_all_benchmarks(&self) {
-		let ref res = self.benchmark_results;
-		let benchmarks = self.computed_benchmarks();
-		print!("\t\t");
-		for b in benchmarks.clone().into_iter() {
-			print!("{:width$}", b, width = 16)
-		}
-		println!("");
-		println!("=======================================================================");
-		for (queryname, v) in res.iter() {
-			print!("{}", queryname);
-			for b in benchmarks.clone().into_iter() {
-				let results = v.get(&b[..]).unwrap(); 
-				match results {
-					&BenchmarkResult::Scalar(f) => print!("\t\t{:1.5}", f),
-					&BenchmarkResult::Vector(_) => panic!("Don't know how to print a vector"),
-					&BenchmarkResult::None => {},		
-				}
-			}
-			println!("");
-		}
-		println!("------------------------------------------------------------------------");
-		print!("MEAN:\t\t");
-		for b in benchmarks.clone().into_iter() {
-			print!("{:width$}", b, width = 16)
-		}
-		println!("");
-		println!("=======================================================================");
-		print!("\t\t");				
-		for b in benchmarks.clone().into_iter() {
-			if let BenchmarkResult::Scalar(score) = self.compute_average_benchmark(&b) {
-				print!("{:1.5}\t\t", score);
-			}
-		}
-		println!("");
-	}
-	fn compute_average_benchmark(&self, benchmark: &String) -> BenchmarkResult {
-		let res = self.benchmark_results.values();
-		let mut acc = 0.0;
-		for v in res.clone().into_iter() {
-			let results = v.get(benchmark).unwrap(); 
-			match results {
-				&BenchmarkResult::Scalar(f) => acc += f,
-				&BenchmarkResult::Vector(_) => panic!("Don't know how to print a vector"),
-				&BenchmarkResult::None => panic!("A query has no computed value"),		
-			};
-		}
-		let c: f32 = res.count() as f32;
-		BenchmarkResult::Scalar(acc / c)
-	}	
 }
 
-impl MetricMAP for RetrievalData {
-	fn average_precision(&self, queryname: &String, results: &Vec<RelevantToken>) -> BenchmarkResult {
-		let hitcount: Vec<f32> = results.iter()
-			.map( |tok| if self.is_this_a_hit(&queryname, tok) { 1.0 } else { 0.0 })
-			.collect();		
-		let num_relevants = match self.relevant_tokens {
-			Some(ref i) => i.get(queryname).unwrap(),
-			None => panic!(format!("MetricMAP::average_precision: Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname)),
-		}.into_iter().count() as f32;
-		let hitsum: Vec<f32> = hitcount.clone().into_iter()
-			.scan(0.0, |state, x| {
-				*state = *state + x;
-				Some(*state)
-			})
-			.enumerate()
-			.map( |(i, x)| x/(i as f32 + 1.0) )
-			.collect();			
-		BenchmarkResult::Scalar(hitcount
-			.iter()
-			.zip(hitsum.iter())
-			.fold(0.0, |acc, (a,b)| acc + a*b ) / num_relevants
-		)
-	}	
+/// Parses a mapping of relevance grades to gains, given as GRADE:GAIN,GRADE:GAIN,...
+fn parse_gains(value: &str) -> HashMap<i32, f32> {
+	value.split(',').map( |pair| match pair.split(':').collect::<Vec<&str>>()[..] {
+		[g, v] => match (g.trim().parse(), v.trim().parse()) {
+			(Ok(g), Ok(v)) => (g, v),
+			_ => panic!("Invalid gain '{}'; expected an integer grade and a number, e.g. 2:3", pair),
+		},
+		_ => panic!("--gain must be given as GRADE:GAIN,GRADE:GAIN,..."),
+	}).collect()
 }
 
-impl MetricPrecisionAtX for RetrievalData {
-	fn precision_at_x(&self, queryname: &String, results: &Vec<RelevantToken>, x: usize) -> BenchmarkResult {
-		let topx = &results[0..x];
-		let hitcount = topx.into_iter()
-			.map( |&ref tok| if self.is_this_a_hit(&queryname, tok) { 1.0 } else { 0.0 })
-			.fold(0.0, |acc, i| acc + i);
-		let mut denominator = x as f32;
-		let num_relevants = match self.relevant_tokens {
-			Some(ref i) => i.get(queryname).unwrap(),
-			None => panic!(format!("MetricPrecisionAtX::precision_at_x: Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname)),
-		}.into_iter().count() as f32;
-		if num_relevants < denominator { denominator = num_relevants }
-		BenchmarkResult::Scalar(hitcount / denominator)
+/// `rusteval convert`: reads a relevance or result file and writes it in another format.
+fn convert(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "to", "output format: icfhr or trec", "FORMAT");
+    opts.optopt("", "from", "input format: icfhr or trec (default: detected from the contents)", "FORMAT");
+    opts.optflag("", "results", "the input is a result file (default: detected from the contents)");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Ok(m) => { m }
+        Err(f) => { panic!("{}", f.to_string()) }
+    };
+    if matches.opt_present("h") || matches.free.len() != 2 || !matches.opt_present("to") {
+        print!("{}", opts.usage(&format!("Usage: {} convert INPUT_FILE OUTPUT_FILE --to FORMAT\n\n'-' stands for the standard input or output.", program)));
+        return;
+    }
+	let (input, output) = (matches.free[0].clone(), matches.free[1].clone());
+	let to = parse_format(&matches.opt_str("to").unwrap());
+	let detected = if input == "-" { None } else { Some(detect_format(&input).expect("Input file could not be read.")) };
+	let from = matches.opt_str("from").map( |f| parse_format(&f) )
+		.or_else( || detected.map( |d| d.0 ) )
+		.expect("--from is required when reading from the standard input");
+	let ft = if matches.opt_present("results") { FileType::ResultsFile }
+		else { detected.map_or(FileType::RelevantTokensFile, |d| d.1) };
+	let mut f = RetrievalData::new();
+	f.set_keep_query_instances(true);
+	match ft {
+		FileType::RelevantTokensFile => f.set_relevants_filename(input),
+		FileType::ResultsFile => f.set_results_filename(input),
 	}
+	parse_input(&mut f, ft, Some(from));
+	let mut out: Box<dyn Write> = match output.as_str() {
+		"-" => Box::new(BufWriter::new(io::stdout())),
+		_ => Box::new(BufWriter::new(File::create(&output).expect("Output file could not be created."))),
+	};
+	match to {
+		FileFormat::XmlICFHR14 => f.write_icfhr14(ft, &mut out),
+		FileFormat::TrecEval => f.write_trec(ft, &mut out),
+	}.and_then( |_| out.flush() ).expect("Output file could not be written.");
 }
 
-impl ParserXmlICFHR14 for RetrievalData {
-	fn parse_tokenstring_fast(&self, tokstr: String) -> RelevantToken {
-		let mut next_token = 0;
-		let mut invalue = 0;
-		let mut endpoints = [[0usize; 2]; 7];
-		
-		let mut tok = RelevantToken::new();
-		for (n, c) in tokstr.chars().enumerate() {
-			match c {
-				'"' => {
-					endpoints[next_token][invalue] = n;
-					invalue = 1 - invalue;
-					if invalue == 0 { next_token += 1; }
-				},
-				_ => {},
-			}
-		}
-		let document = String::from(&tokstr[endpoints[0][0]+1..endpoints[0][1]]);
-		let x = String::from(&tokstr[endpoints[1][0]+1..endpoints[1][1]]).parse().unwrap();
-		let y = String::from(&tokstr[endpoints[2][0]+1..endpoints[2][1]]).parse().unwrap();
-		let w = String::from(&tokstr[endpoints[3][0]+1..endpoints[3][1]]).parse().unwrap();
-		let h = String::from(&tokstr[endpoints[4][0]+1..endpoints[4][1]]).parse().unwrap();
-		if endpoints[5][1] > 0 {
-			let imp_char_5 = tokstr.chars().nth(endpoints[5][0]-2).unwrap();
-			if imp_char_5 == 'e' // The last character of 'Relevance'
-				{ 
-					let relv = String::from(&tokstr[endpoints[5][0]+1..endpoints[5][1]]).parse().unwrap(); 
-					tok.set_relevance(relv);
-				}
-			else if imp_char_5 == 't' // The last character of 'Text'
-				{ 
-					let transcription = String::from(&tokstr[endpoints[5][0]+1..endpoints[5][1]]); 
-					tok.set_transcription(transcription);
-				}
-			else
-				{ panic!("Unidentified token field"); }
-			if endpoints[6][1] > 0 {
-				let imp_char_6 = tokstr.chars().nth(endpoints[6][0]-2).unwrap();				
-				if imp_char_6 == 'e' // The last character of 'Relevance'
-					{ 
-						let relv = String::from(&tokstr[endpoints[6][0]+1..endpoints[6][1]]).parse().unwrap(); 
-						tok.set_relevance(relv);
-					}
-				else if imp_char_6 == 't' // The last character of 'Text'
-					{ 
-						let transcription = String::from(&tokstr[endpoints[6][0]+1..endpoints[6][1]]); 
-						tok.set_transcription(transcription);
-					}
-				else
-					{ panic!("Unidentified token field"); }		
-			}
-		}
-		let mut rect = Rectangle::new();
-		rect.set_min(x, y).set_size(w, h);
-		tok.set_tokenid(TokenId::BoundingBox(rect));
-		tok.set_document(document);
-		tok
-	}
-	fn parse_tokenstring(&self, tokstr: String) -> RelevantToken {		
-		let mut token_traits_hash = HashMap::new();
-		token_traits_hash.insert("bbox", 		r#"x="(\d+)" y="(\d+)" width="(\d+)" height="(\d+)""#);
-		token_traits_hash.insert("id", 	 		r#"id="(\d+)"#);
-		token_traits_hash.insert("docname", 	r#"word document="(.*?)""#);
-		token_traits_hash.insert("relevance",	r#"Relevance="([\d\.]+)""#);
-		let token_traits_hash = token_traits_hash;
-		
-		let mut tok = RelevantToken::new();			
-		for (&k, &val) in &token_traits_hash {
-			let re = Regex::new(val).unwrap();
-			for l in re.captures_iter(&tokstr[..]) {
-				match k {
-					"bbox"		=> {
-						let mut rect = Rectangle::new();
-						let x: u32 = String::from(l.at(1).unwrap()).parse().expect("oops");
-						let y: u32 = String::from(l.at(2).unwrap()).parse().expect("oops");
-						let w: u32 = String::from(l.at(3).unwrap()).parse().expect("oops");
-						let h: u32 = String::from(l.at(4).unwrap()).parse().expect("oops");
-						rect.set_min(x, y).set_size(w, h);
-						tok.set_tokenid(TokenId::BoundingBox(rect));
-					},
-					"id"			 => panic!("This isn't implemented yet"),
-					"docname"		 => { tok.set_document(String::from(l.at(1).unwrap())); },
-					"relevance" 	 => {
-						let relv: f32 = String::from(l.at(1).unwrap()).parse().expect("oops");
-						tok.set_relevance(relv);
-					},
-					_				 => panic!("Don't know how to handle this token trait."),
-				}
-			}
-		}
-		tok
-	}
-
-	fn parse_file(&mut self, ft: FileType) {
-		enum ParsingState {
-			WaitingNextQuery,
-			InQueryBlock(String),
-		};
-		let (filename, re_querystarts, re_queryends) = match ft {
-			FileType::RelevantTokensFile => ( 
-				self.relevant_tokens_filename.clone(),				
-				Regex::new(r#"<GTRel queryid="([\s\S]*?)">"#).unwrap(),
-				Regex::new(r#"</GTRel>"#).unwrap(),
-
-				),
-			FileType::ResultsFile => (
-				self.result_tokens_filename.clone(),				
-				Regex::new(r#"<Rel queryid="([\s\S]*?)">"#).unwrap(),
-				Regex::new(r#"</Rel>"#).unwrap(),				
-			),
-		};
-		let filename = match filename { //see http://stackoverflow.com/a/28035122/5615276
-			Some(ref f) => f,
-			None => panic!("oops"),
-		};
-		let f = File::open(filename).expect("Input xml could not be read.");
-		let f = BufReader::new(f);
-
-		let mut relevant_tokens: Vec<RelevantToken> = Vec::new();
-		let mut res = HashMap::new();
-		
-		let mut state = ParsingState::WaitingNextQuery;		
-		let mut re_state_must_change = re_querystarts.clone();
-		
-		for buffer in f.lines() {
-			let current_line = buffer.unwrap();
-			match re_state_must_change.captures_iter(&current_line[..]).next() {
-				Some(captured_queryname) => match state {
-					ParsingState::WaitingNextQuery => {
-						state = ParsingState::InQueryBlock(String::from(captured_queryname.at(1).unwrap()));
-						re_state_must_change = re_queryends.clone();
-					},
-					ParsingState::InQueryBlock(query_name) => {
-						match ft {
-							FileType::ResultsFile => {
-								self.store_all_numerical_results(&query_name, &relevant_tokens); 
-							},							
-							_ => {},
-						};
-						res.insert(query_name.clone(), relevant_tokens);
-						relevant_tokens = Vec::new();
-						state = ParsingState::WaitingNextQuery;
-						re_state_must_change = re_querystarts.clone();
-					},
-				},
-				None => match state {
-					ParsingState::WaitingNextQuery => {},
-					ParsingState::InQueryBlock(_) => {
-						relevant_tokens.push(self.parse_tokenstring_fast(String::from(current_line.clone())));						
-					},
-				},
-			};
-		}
-		match ft {
-			FileType::RelevantTokensFile => self.relevant_tokens = Some(res),
-			FileType::ResultsFile => self.result_tokens = Some(res),
-		};
+/// `rusteval make-qrels`: builds relevance judgements from a transcribed word segmentation.
+fn make_qrels(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "to", "output format: icfhr or trec (default: icfhr)", "FORMAT");
+    opts.optopt("", "normalize", "comma-separated normalizations applied before matching: case, punctuation, diacritics (default: none)", "LIST");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Ok(m) => { m }
+        Err(f) => { panic!("{}", f.to_string()) }
+    };
+    if matches.opt_present("h") || matches.free.len() != 3 {
+        print!("{}", opts.usage(&format!("Usage: {} make-qrels ANNOTATION_FILE QUERY_FILE OUTPUT_FILE [--normalize LIST]\n\n'-' stands for the standard output.", program)));
+        return;
+    }
+	let to = matches.opt_str("to").map_or(FileFormat::XmlICFHR14, |f| parse_format(&f));
+	let norm = matches.opt_str("normalize").map_or(Normalization::default(), |l| Normalization::from_names(&l)
+		.unwrap_or_else( || panic!("Unknown normalization in '{}'; expected case, punctuation or diacritics", l) ));
+	let words = read_annotations(&matches.free[0]);
+	let queries = read_queries(&matches.free[1]);
+	let (qrels, unmatched) = qrels::make_qrels(&words, &queries, norm);
+	for queryname in unmatched {
+		eprintln!("Warning: no relevant word for query {}; it is left out", queryname);
 	}
+	let output = &matches.free[2];
+	let mut out: Box<dyn Write> = match output.as_str() {
+		"-" => Box::new(BufWriter::new(io::stdout())),
+		_ => Box::new(BufWriter::new(File::create(output).expect("Output file could not be created."))),
+	};
+	match to {
+		FileFormat::XmlICFHR14 => qrels.write_icfhr14(FileType::RelevantTokensFile, &mut out),
+		FileFormat::TrecEval => qrels.write_trec(FileType::RelevantTokensFile, &mut out),
+	}.and_then( |_| out.flush() ).expect("Output file could not be written.");
 }
 
-fn load_fixtures(load_results: bool) -> RetrievalData {
+/// `rusteval stats`: prints summary statistics of a relevance file.
+fn stats(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optflag("", "json", "print the statistics as JSON");
+    opts.optopt("", "format", "format of the relevance file: icfhr or trec (default: detected from the contents)", "FORMAT");
+    opts.optflag("", "allow-missing-source", "read an index file even if the relevance file it was built from is gone");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Ok(m) => { m }
+        Err(f) => { panic!("{}", f.to_string()) }
+    };
+    if matches.opt_present("h") || matches.free.len() != 1 {
+        print!("{}", opts.usage(&format!("Usage: {} stats RELEVANCE_FILE [--json]", program)));
+        return;
+    }
 	let mut f = RetrievalData::new();
-	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
-	f.parse_file(FileType::RelevantTokensFile);	
-	if load_results {
-		f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
-		f.parse_file(FileType::ResultsFile);
+	f.set_allow_missing_index_source(matches.opt_present("allow-missing-source"));
+	f.set_relevants_filename(matches.free[0].clone());
+	parse_input(&mut f, FileType::RelevantTokensFile, matches.opt_str("format").map( |f| parse_format(&f) ));
+	let stats = GroundTruthStats::new(&f);
+	if matches.opt_present("json") {
+		println!("{}", stats.to_json());
+	} else {
+		stats.print();
 	}
-	f
 }
 
-fn load_fixtures_bentham(load_results: bool) -> RetrievalData {
+/// `rusteval check-run`: reports problems of a result file; exits with status 1 on hard errors.
+fn check(program: &str, args: &[String]) {
+    let mut opts = Options::new();
+    opts.optopt("", "depth", "warn about result lists with fewer than N tokens", "N");
+    opts.optopt("", "format", "format of the relevance and result files: icfhr or trec (default: detected from the contents)", "FORMAT");
+    opts.optopt("", "collection", "report documents that are not in the collection manifest FILE and boxes beyond their page", "FILE");
+    opts.optflag("", "allow-missing-source", "read an index file even if the relevance file it was built from is gone");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Ok(m) => { m }
+        Err(f) => { panic!("{}", f.to_string()) }
+    };
+    if matches.opt_present("h") || matches.free.len() != 2 {
+        print!("{}", opts.usage(&format!("Usage: {} check-run RELEVANCE_FILE RESULT_FILE [--depth N]", program)));
+        return;
+    }
+	let format = matches.opt_str("format").map( |f| parse_format(&f) );
 	let mut f = RetrievalData::new();
-	f.set_relevants_filename(String::from("fixtures/TRACK_I_Bentham_ICFHR2014.RelevanceJudgements.xml"));
-	f.parse_file(FileType::RelevantTokensFile);	
-	if load_results {
-		File::open(String::from("fixtures/G1_TRACK_I_Bentham.xml"))
-		.expect("You have to unzip the fixture XML first; please follow the instructions found in README.md");
-		f.set_results_filename(String::from("fixtures/G1_TRACK_I_Bentham.xml"));
-		f.parse_file(FileType::ResultsFile);
+	f.set_allow_missing_index_source(matches.opt_present("allow-missing-source"));
+	f.set_relevants_filename(matches.free[0].clone());
+	parse_input(&mut f, FileType::RelevantTokensFile, format);
+	if let Some(fname) = matches.opt_str("collection") {
+		f.set_collection(Collection::from_file(&fname, BoundsPolicy::Reject));
 	}
-	f
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checkquerynames() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	let num_queries = res.iter().count();
-	assert!(res.contains_key("sb0000"));
-	assert!(res.contains_key("sb0001"));
-	assert_eq!(num_queries, 2);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_1() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert!(res.values().all( |relevants| relevants.len() == 9 ));
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_x() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	for token in res.get("sb0000").unwrap().iter() {
-		match token.id {
-			//Regarding 'ref', see http://stackoverflow.com/a/28159407/5615276
-			// and http://rustbyexample.com/scope/borrow/ref.html
-			TokenId::BoundingBox(ref bb) => println!("{:?}", bb.min.get_x()),
-			TokenId::NumericId(_) 	 => assert!(false),
-		}
-	}
-	assert_eq!(res.get("sb0000").unwrap().iter().fold(0, |acc, token|
-		match token.id {
-			TokenId::BoundingBox(ref bb) => acc + bb.min.get_x(),
-			TokenId::NumericId(_) 	 => acc,
-		}
-	), 6435);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_y() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
-		match token.id {
-			TokenId::BoundingBox(ref bb) => acc + bb.min.get_y(),
-			TokenId::NumericId(_) 	 => acc,
-		}
-	), 10921);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_width() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
-		match token.id {
-			TokenId::BoundingBox(ref bb) => acc + bb.width(),
-			TokenId::NumericId(_) 	 => acc,
-		}
-	), 2175);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_height() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
-		match token.id {
-			TokenId::BoundingBox(ref bb) => acc + bb.height(),
-			TokenId::NumericId(_) 	 => acc,
-		}
-	), 987);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_relevance_0() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert_eq!(res.get("sb0000").unwrap().iter().fold(0.0, |acc, token|
-		acc + token.get_relevance()
-	), 8.0);
-}
-
-#[test]
-fn test_parserelevants_type_icfhr14xml_checksums_relevance_1() {
-	let f = load_fixtures(false);
-	let res = f.relevant_tokens.unwrap();
-	assert_eq!(res.get("sb0001").unwrap().iter().fold(0.0, |acc, token|
-		acc + token.get_relevance()
-	), 8.0); 
-	//NOTE: there is a syntax error on the sample file ("elevance" instead of "Relevance")
-	// This version of the parser will read relevance despite the syntax error;
-	// a previous version of this souce didn't though, so the '8.0' on the assert had to be '7.0'.
-}
-
-#[test]
-fn test_parseresults_type_icfhr14xml_checksums_width() {
-	let f = load_fixtures(true);
-	let res = f.result_tokens.unwrap();
-	let ref token = res.get("sb0001").unwrap()[0]; 
-	match token.id {
-		TokenId::BoundingBox(ref bb) => assert_eq!(bb.width(), 180),
-		TokenId::NumericId(_) 	 => assert!(false),
-	};
-	let ref token = res.get("sb0001").unwrap().last().unwrap(); 
-	match token.id {
-		TokenId::BoundingBox(ref bb) => assert_eq!(bb.width(), 278),
-		TokenId::NumericId(_) 	 => assert!(false),
-	};
-}
-
-#[test]
-fn test_precision_at_x() {
-	let f = load_fixtures(true);
-	let res = f.benchmark_results;
-	let q0 = res.get("sb0000").unwrap();
-	let q1 = res.get("sb0001").unwrap();
-	match q0.get("precAt10").unwrap() { 
-		&BenchmarkResult::Scalar(x) => assert!((x - 0.55555).abs() < 0.001),
-		_ => assert!(false),
-	};
-	match q1.get("precAt10").unwrap() { 
-		&BenchmarkResult::Scalar(x) => assert!((x - 0.44444).abs() < 0.001),
-		_ => assert!(false),
-	};	
-	match q0.get("precAt5").unwrap() { 
-		&BenchmarkResult::Scalar(x) => assert_eq!(x, 1.0),
-		_ => assert!(false),
-	};
-	match q1.get("precAt5").unwrap() { 
-		&BenchmarkResult::Scalar(x) => assert_eq!(x, 0.8),
-		_ => assert!(false),
-	};		
-}
-
-#[test]
-fn test_bentham_precision() {
-	let f = load_fixtures_bentham(true);
-	let benchmarks = ["precAt10", "precAt5", "ap"];
-	// These results coincide with the ones reported at the ICFHR'14 competition, [Pratikakis et al. 2014]
-	let should_compute = [0.60267615, 0.73812526, 0.5240239];	
-	for (i, &b) in benchmarks.clone().into_iter().enumerate() {
-		if let BenchmarkResult::Scalar(score) = f.compute_average_benchmark(&String::from(b)) {
-			println!("{} should have been {}, computed is {}", b, should_compute[i], score);
-			assert!((should_compute[i] - score).abs() < 0.001); 
-		}
+	let run = &matches.free[1];
+	let run_format = format.unwrap_or_else( || if run == "-" { FileFormat::XmlICFHR14 }
+		else { detect_format(run).expect("Result file could not be read.").0 } );
+	let depth = matches.opt_str("depth").map( |d| d.parse().expect("--depth must be a non-negative integer") );
+	let report = check_run(&f, run, run_format, depth).expect("Result file could not be read.");
+	report.print();
+	if report.num_errors() > 0 {
+		process::exit(1);
 	}
 }
 
-
-fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} RELEVANCE_FILE RESULT_FILE [options]", program);
-    print!("{}", opts.usage(&brief));
-}
-
-fn main() {	
+fn main() {
 	let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
+    if args.len() > 1 && args[1] == "index" {
+        return index(&program, &args[2..]);
+    }
+    if args.len() > 1 && args[1] == "convert" {
+        return convert(&program, &args[2..]);
+    }
+    if args.len() > 1 && args[1] == "make-qrels" {
+        return make_qrels(&program, &args[2..]);
+    }
+    if args.len() > 1 && args[1] == "stats" {
+        return stats(&program, &args[2..]);
+    }
+    if args.len() > 1 && args[1] == "check-run" {
+        return check(&program, &args[2..]);
+    }
 
     let mut opts = Options::new();
-    //opts.optopt("s", "result", "set result file", "NAME");
-    //opts.optopt("l", "relevance", "set relevance file", "NAME");	
+    opts.optopt("", "depth", "only take into account the first N retrieved tokens of each query", "N");
+    opts.optopt("", "min-relevance", "treat relevant tokens with relevance (or grade) below R as non-relevant", "R");
+    opts.optopt("", "gain", "map relevance grades to the gains of nDCG, e.g. 0:0,1:1,2:3,3:7", "MAP");
+    opts.optopt("", "queries", "only evaluate the queries listed in FILE", "FILE");
+    opts.optopt("", "groups", "also report mean values for the query groups defined in FILE", "FILE");
+    opts.optopt("", "documents", "also report relevant tokens, hits, misses and false positives among the top K results per document", "K");
+    opts.optopt("", "document-prefix", "aggregate documents by the first N '_'-separated fields of their name", "N");
+    opts.optopt("", "query-instances", "exclude the query instances defined in FILE from the evaluation", "FILE");
+    opts.optopt("", "format", "format of the relevance and result files: icfhr or trec (default: detected from the contents)", "FORMAT");
+    opts.optflag("", "streaming", "evaluate each query as it is read, without keeping the result lists in memory");
+    opts.optopt("", "overlap", "match regions on the same document whose intersection over union is at least T (default: only identical regions match)", "T");
+    opts.optopt("", "document-level", "evaluate which documents contain each query, ranked by their first or best scoring (max) result", "first|max");
+    opts.optopt("", "nms", "remove results overlapping a better scoring result on the same document with an intersection over union of at least T", "T");
+    opts.optopt("", "collection", "check tokens against the pages of the collection manifest FILE", "FILE");
+    opts.optflag("", "reject-out-of-bounds", "remove boxes extending beyond their page instead of clipping them");
+    opts.optopt("", "scale", "multiply the coordinates of result boxes by S, or by SX and SY", "S|SX,SY");
+    opts.optopt("", "offset", "add DX and DY to the coordinates of result boxes, after scaling", "DX,DY");
+    opts.optopt("", "transform", "transform result boxes on the pages listed in FILE instead, by page", "FILE");
+    opts.optopt("", "rounding", "rounding of transformed boxes: nearest, outward or inward (default: nearest)", "MODE");
+    opts.optflag("", "allow-missing-source", "read an index file even if the relevance file it was built from is gone");
     opts.optflag("h", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => { panic!("{}", f.to_string()) }
     };
-    if matches.opt_present("h") {
+    if matches.opt_present("h") || matches.free.len() < 2 {
         print_usage(&program, opts);
         return;
     }
-    //let input = 
-	if !matches.free.is_empty() {
-        matches.free[0].clone()
-    } else {
-        print_usage(&program, opts);
-        return;
-    };
 
-	let relevance_file = String::from(args[1].clone());
-	let result_file = String::from(args[2].clone());
+	let relevance_file = matches.free[0].clone();
+	let result_file = matches.free[1].clone();
 	let mut f = RetrievalData::new();
-	//f.set_relevants_filename(String::from("/tmp/gt.xml"));
-	//f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	if let Some(depth) = matches.opt_str("depth") {
+		f.set_depth(depth.parse().expect("--depth must be a non-negative integer"));
+	}
+	if let Some(r) = matches.opt_str("min-relevance") {
+		f.set_min_relevance(r.parse().expect("--min-relevance must be a number"));
+	}
+	if let Some(g) = matches.opt_str("gain") {
+		f.set_gains(parse_gains(&g));
+	}
+	if let Some(t) = matches.opt_str("overlap") {
+		f.set_overlap_threshold(t.parse().expect("--overlap must be a number"));
+	}
+	if let Some(t) = matches.opt_str("nms") {
+		f.set_non_maximum_suppression(t.parse().expect("--nms must be a number"));
+	}
+	if let Some(r) = matches.opt_str("document-level") {
+		f.set_document_level(DocumentRanking::from_name(&r)
+			.unwrap_or_else( || panic!("Unknown document ranking '{}'; expected 'first' or 'max'", r) ));
+	}
+	if let Some(fname) = matches.opt_str("queries") {
+		f.set_selected_queries(read_query_list(&fname));
+	}
+	if let Some(fname) = matches.opt_str("groups") {
+		f.set_query_groups(read_query_groups(&fname));
+	}
+	if let Some(fname) = matches.opt_str("query-instances") {
+		f.set_query_instances_filename(fname);
+		f.parse_query_instances_file();
+	}
+	if let Some(fname) = matches.opt_str("collection") {
+		let policy = if matches.opt_present("reject-out-of-bounds") { BoundsPolicy::Reject } else { BoundsPolicy::Clip };
+		f.set_collection(Collection::from_file(&fname, policy));
+	}
+	if matches.opt_present("scale") || matches.opt_present("offset") || matches.opt_present("transform") {
+		let default = if matches.opt_present("scale") || matches.opt_present("offset") {
+			Some(Transform::new(
+				matches.opt_str("scale").map_or((1.0, 1.0), |s| parse_pair(&s, "scale", true)),
+				matches.opt_str("offset").map_or((0.0, 0.0), |o| parse_pair(&o, "offset", false))))
+		} else { None };
+		let rounding = matches.opt_str("rounding").map_or(Rounding::Nearest, |r| Rounding::from_name(&r)
+			.unwrap_or_else( || panic!("Unknown rounding '{}'; expected 'nearest', 'outward' or 'inward'", r) ));
+		let mut transforms = Transforms::new(default, rounding);
+		if let Some(fname) = matches.opt_str("transform") {
+			transforms.read_file(&fname);
+		}
+		f.set_transforms(transforms);
+	}
+	let format = matches.opt_str("format").map( |f| parse_format(&f) );
+	f.set_allow_missing_index_source(matches.opt_present("allow-missing-source"));
 	f.set_relevants_filename(relevance_file);
-	f.parse_file(FileType::RelevantTokensFile);
-	//println!("{:?}", f.relevant_tokens);
-
-	//f.set_results_filename(String::from("/tmp/res.xml"));
-	//f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
-	f.set_results_filename(result_file);	
-	f.parse_file(FileType::ResultsFile);
-	//println!("{:?}", f.result_tokens);
-	//println!("{:?}", f.benchmark_results);
-	f.print_all_benchmarks();	
-}
\ No newline at end of file
+	parse_input(&mut f, FileType::RelevantTokensFile, format);
+	let documents: Option<usize> = matches.opt_str("documents")
+		.map( |k| k.parse().expect("--documents must be a positive integer") );
+	if matches.opt_present("streaming") {
+		f.set_streaming(documents);
+	}
+	f.set_results_filename(result_file);
+	parse_input(&mut f, FileType::ResultsFile, format);
+	f.print_all_benchmarks();
+	if let Some(removed) = f.transforms().map( |t| t.removed ).filter( |&n| n > 0 ) {
+		eprintln!("Warning: {} result boxes were transformed to before the page origin and removed", removed);
+	}
+	if let Some(collection) = f.collection() {
+		collection.print();
+	}
+	if let Some(k) = documents {
+		let prefix_fields = matches.opt_str("document-prefix")
+			.map( |n| n.parse().expect("--document-prefix must be a positive integer") );
+		f.print_document_breakdown(k, prefix_fields);
+	}
+}
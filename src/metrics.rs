@@ -0,0 +1,894 @@
+use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::cmp::Ordering;
+
+use tokens::RelevantToken;
+use tokens::TokenId;
+use polygons::Polygon;
+use spatial::SpatialIndex;
+use cache::match_key;
+use aggregation::DocumentRanking;
+use retrieval::RetrievalData;
+
+#[derive(Debug)]
+pub enum BenchmarkResult {
+	Scalar(f32),
+	Vector(Vec<f32>),
+	None,
+}
+
+impl BenchmarkResult {
+	pub fn scalar(&self) -> Option<f32> {
+		match *self {
+			BenchmarkResult::Scalar(f) => Some(f),
+			_ => None,
+		}
+	}
+}
+
+pub trait MetricPrecisionAtX {
+	fn precision_at_x(&self, queryname: &str, results: &[RelevantToken], x: usize) -> BenchmarkResult;
+	fn precision_at_5(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.precision_at_x(queryname, results, 5)
+	}
+	fn precision_at_10(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.precision_at_x(queryname, results, 10)
+	}
+}
+
+pub trait MetricMAP {
+	fn num_recall_points(&self) -> usize { 11 }
+	fn average_precision(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult;
+}
+
+/// Tie-aware variants of the metrics above, following [McSherry & Najork 2008].
+/// Consecutive results sharing the same score form a tie group, and each metric
+/// is replaced by its expected value over all orderings within the tie groups.
+/// Tie groups are formed before the depth cut-off, so that a group straddling it
+/// counts in proportion to its part above the cut-off.
+pub trait MetricTieAware {
+	fn expected_precision_at_x(&self, queryname: &str, results: &[RelevantToken], x: usize) -> BenchmarkResult;
+	fn expected_precision_at_5(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.expected_precision_at_x(queryname, results, 5)
+	}
+	fn expected_precision_at_10(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.expected_precision_at_x(queryname, results, 10)
+	}
+	fn expected_average_precision(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult;
+}
+
+/// Normalized discounted cumulative gain [Järvelin & Kekäläinen 2002], for graded relevance.
+/// Each relevant token is credited once, to the first result that matches it.
+pub trait MetricNDCG {
+	fn gain(&self, token: &RelevantToken) -> f32;
+	fn ndcg_at_x(&self, queryname: &str, results: &[RelevantToken], x: Option<usize>) -> BenchmarkResult;
+	fn ndcg(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.ndcg_at_x(queryname, results, None)
+	}
+	fn ndcg_at_10(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		self.ndcg_at_x(queryname, results, Some(10))
+	}
+}
+
+pub trait Benchmark : MetricPrecisionAtX + MetricMAP + MetricTieAware + MetricNDCG {
+	fn tokens_match(&self, a: &RelevantToken, b: &RelevantToken) -> bool;
+	fn is_relevant(&self, token: &RelevantToken) -> bool;
+	fn num_relevants(&self, queryname: &str) -> usize;
+	fn cut_off<'a>(&self, results: &'a [RelevantToken]) -> &'a [RelevantToken];
+	fn exclude_query_instance(&self, queryname: &str, tokens: &mut Vec<RelevantToken>);
+	fn is_this_a_hit(&self, queryname: &str, token: &RelevantToken) -> bool;
+	fn store_all_numerical_results(&mut self, queryname: &str, hitlist: &[RelevantToken]);
+	fn computed_benchmarks(&self) -> Vec<String>;
+	fn print_all_benchmarks(&self);
+	fn compute_average_benchmark(&self, benchmark: &str) -> BenchmarkResult;
+	fn compute_group_average_benchmark(&self, benchmark: &str, group: &str) -> BenchmarkResult;
+}
+
+/// Counts of relevant and retrieved tokens on a single document (or group of documents).
+#[derive(Debug, Default)]
+pub struct DocumentCounts {
+	pub relevant:			usize,
+	pub hits:				usize,
+	pub misses:				usize,
+	pub false_positives:	usize,
+}
+
+pub trait DocumentBreakdown : Benchmark {
+	fn document_breakdown(&self, k: usize, prefix_fields: Option<usize>) -> BTreeMap<String, DocumentCounts>;
+	fn print_document_breakdown(&self, k: usize, prefix_fields: Option<usize>);
+}
+
+/// The region of a token, as a polygon, for overlap matching.
+pub(crate) fn region(tok: &RelevantToken) -> Option<Polygon> {
+	match tok.id {
+		TokenId::BoundingBox(ref bb) => Some(Polygon::from_rectangle(bb)),
+		TokenId::Polygon(ref poly) => Some(poly.clone()),
+		_ => None,
+	}
+}
+
+impl RetrievalData {
+	/// The positions of the relevant tokens of a query that `token` may match, if they can be narrowed
+	/// down: by the spatial index with an overlap threshold, or by the match keys of an index file.
+	fn candidates(&self, queryname: &str, token: &RelevantToken) -> Option<Vec<usize>> {
+		match (&self.spatial_index, self.overlap_threshold, self.match_keys.get(queryname)) {
+			(Some((q, index)), _, _) if q == queryname => index.candidates(token),
+			(_, None, Some(keys)) => {
+				let key = match_key(token);
+				let first = keys.partition_point( |&(k, _)| k < key );
+				let mut found: Vec<usize> = keys[first..].iter().take_while( |&&(k, _)| k == key ).map( |&(_, i)| i ).collect();
+				found.sort_unstable();
+				Some(found)
+			},
+			_ => None,
+		}
+	}
+}
+
+/// Whether two regions have an intersection over union of at least `threshold`.
+fn regions_overlap(a: &Polygon, b: &Polygon, threshold: f32) -> bool {
+	if a.bounding_box().intersection(&b.bounding_box()).is_none() { return false; }
+	// Identical regions may fall short of an IoU of exactly 1 through rounding
+	a.iou(b) >= threshold as f64 - 1e-9
+}
+
+/// Whether two spans of offsets, inclusive, have an intersection over union of at least `threshold`.
+fn spans_overlap(a: (u32, u32), b: (u32, u32), threshold: f32) -> bool {
+	let intersection = (a.1.min(b.1) as f64 - a.0.max(b.0) as f64 + 1.0).max(0.0);
+	let union = (a.1 - a.0) as f64 + (b.1 - b.0) as f64 + 2.0 - intersection;
+	intersection / union >= threshold as f64 - 1e-9
+}
+
+impl Benchmark for RetrievalData {
+	/// Bounding boxes match when they are identical, unless an overlap threshold is set; polygons are
+	/// compared with boxes and other polygons on the same document by their intersection over union.
+	/// Lines match lines with the same id and the spans within them; spans are compared like boxes.
+	fn tokens_match(&self, a: &RelevantToken, b: &RelevantToken) -> bool {
+		match (&a.id, &b.id) {
+			(TokenId::BoundingBox(a_box), TokenId::BoundingBox(b_box)) => match self.overlap_threshold {
+				None => a_box == b_box,
+				Some(t) => a.get_document() == b.get_document() && a_box.iou(b_box) >= t as f64 - 1e-9,
+			},
+			// A line matches the spans within it, e.g. a retrieved line is a hit if it contains a relevant word
+			(TokenId::Line(i), TokenId::Line(j)) |
+			(TokenId::Line(i), TokenId::Span(j, _, _)) |
+			(TokenId::Span(i, _, _), TokenId::Line(j)) => a.get_document() == b.get_document() && i == j,
+			(TokenId::Span(i, a0, a1), TokenId::Span(j, b0, b1)) => a.get_document() == b.get_document() && i == j &&
+				match self.overlap_threshold {
+					None => (a0, a1) == (b0, b1),
+					Some(t) => spans_overlap((*a0, *a1), (*b0, *b1), t),
+				},
+			(TokenId::NumericId(i), TokenId::NumericId(j)) => i == j,
+			(TokenId::StringId(i), TokenId::StringId(j)) => i == j,
+			_ => match (region(a), region(b)) {
+				(Some(a_region), Some(b_region)) => a.get_document() == b.get_document() &&
+					regions_overlap(&a_region, &b_region, self.overlap_threshold.unwrap_or(1.0)),
+				_ => false,
+			},
+		}
+	}
+	/// Tokens with a grade of 0 or less are non-relevant, unless a lower threshold is set.
+	fn is_relevant(&self, token: &RelevantToken) -> bool {
+		match self.min_relevance {
+			Some(r) => token.get_relevance() >= r,
+			None => match token.get_grade() {
+				Some(g) => g > 0,
+				None => true,
+			},
+		}
+	}
+	fn num_relevants(&self, queryname: &str) -> usize {
+		match self.relevant_tokens {
+			Some(ref i) => i.get(queryname).unwrap(),
+			None => panic!("Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname),
+		}.iter().filter( |tok| self.is_relevant(tok) ).count()
+	}
+	fn cut_off<'a>(&self, results: &'a [RelevantToken]) -> &'a [RelevantToken] {
+		match self.depth {
+			Some(d) if d < results.len() => &results[..d],
+			_ => results,
+		}
+	}
+	fn exclude_query_instance(&self, queryname: &str, tokens: &mut Vec<RelevantToken>) {
+		if let Some(query_token) = self.query_instances.get(queryname) {
+			tokens.retain( |tok| !self.tokens_match(query_token, tok) );
+		}
+	}
+	fn is_this_a_hit(&self, queryname: &str, token: &RelevantToken) -> bool {
+		let relevants = match self.relevant_tokens {
+			Some(ref i) => i.get(queryname).unwrap(),
+			None => panic!("Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname),
+		};
+		match self.candidates(queryname, token) {
+			Some(c) => c.iter().map( |&i| &relevants[i] ).any( |pred| self.is_relevant(pred) && self.tokens_match(pred, token)),
+			None => relevants.iter().any( |pred| self.is_relevant(pred) && self.tokens_match(pred, token)),
+		}
+	}
+	fn store_all_numerical_results(&mut self, queryname: &str, hitlist: &[RelevantToken]) {
+		// With an overlap threshold, regions only match relevant regions that intersect them on the same document
+		self.spatial_index = match (self.overlap_threshold, self.relevant_tokens.as_ref().and_then( |r| r.get(queryname) )) {
+			(Some(_), Some(relevants)) => Some((String::from(queryname), SpatialIndex::new(relevants))),
+			_ => None,
+		};
+		let mut res = HashMap::new();
+		res.insert(
+			String::from("precAt5"),
+			self.precision_at_5(queryname, hitlist),
+		);
+		res.insert(
+			String::from("precAt10"),
+			self.precision_at_10(queryname, hitlist),
+		);
+		res.insert(
+			String::from("ap"),
+			self.average_precision(queryname, hitlist),
+		);
+		res.insert(
+			String::from("tiePrecAt5"),
+			self.expected_precision_at_5(queryname, hitlist),
+		);
+		res.insert(
+			String::from("tiePrecAt10"),
+			self.expected_precision_at_10(queryname, hitlist),
+		);
+		res.insert(
+			String::from("tieAp"),
+			self.expected_average_precision(queryname, hitlist),
+		);
+		res.insert(
+			String::from("ndcg"),
+			self.ndcg(queryname, hitlist),
+		);
+		res.insert(
+			String::from("ndcgAt10"),
+			self.ndcg_at_10(queryname, hitlist),
+		);
+		self.spatial_index = None;
+		self.benchmark_results.insert(String::from(queryname), res);
+	}
+	fn computed_benchmarks(&self) -> Vec<String> {
+		self.benchmark_results.values().next().unwrap().keys().cloned().collect()
+	}
+	fn print_all_benchmarks(&self) {
+		let res = &self.benchmark_results;
+		let benchmarks = self.computed_benchmarks();
+		if let Some(ref nms) = self.nms {
+			let (results, removed) = nms.totals();
+			println!("PREPROCESSING\tnon-maximum suppression (IoU >= {}, per document, by score): {} of {} results removed",
+				nms.threshold, removed, results);
+		}
+		if let Some(ranking) = self.document_ranking {
+			println!("PREPROCESSING\tdocument-level evaluation: tokens aggregated by document, results ranked by {} word",
+				match ranking { DocumentRanking::FirstOccurrence => "first", DocumentRanking::MaxScore => "best scoring" });
+		}
+		if self.nms.is_some() || self.document_ranking.is_some() { println!(); }
+		print!("\t\t");
+		for b in &benchmarks {
+			print!("{:width$}", b, width = 16)
+		}
+		println!();
+		println!("=======================================================================");
+		for (queryname, v) in res.iter() {
+			print!("{}", queryname);
+			for b in &benchmarks {
+				match *v.get(b).unwrap() {
+					BenchmarkResult::Scalar(f) => print!("\t\t{:1.5}", f),
+					BenchmarkResult::Vector(_) => panic!("Don't know how to print a vector"),
+					BenchmarkResult::None => {},
+				}
+			}
+			println!();
+		}
+		println!("------------------------------------------------------------------------");
+		print!("MEAN:\t\t");
+		for b in &benchmarks {
+			print!("{:width$}", b, width = 16)
+		}
+		println!();
+		println!("=======================================================================");
+		print!("\t\t");
+		for b in &benchmarks {
+			if let BenchmarkResult::Scalar(score) = self.compute_average_benchmark(b) {
+				print!("{:1.5}\t\t", score);
+			}
+		}
+		println!();
+		for group in self.group_labels() {
+			println!("------------------------------------------------------------------------");
+			print!("MEAN ({}):\t", group);
+			for b in &benchmarks {
+				print!("{:width$}", b, width = 16)
+			}
+			println!();
+			println!("=======================================================================");
+			print!("\t\t");
+			for b in &benchmarks {
+				match self.compute_group_average_benchmark(b, &group) {
+					BenchmarkResult::Scalar(score) => print!("{:1.5}\t\t", score),
+					_ => print!("-\t\t"),
+				}
+			}
+			println!();
+		}
+	}
+	fn compute_average_benchmark(&self, benchmark: &str) -> BenchmarkResult {
+		average_benchmark(benchmark, self.benchmark_results.values())
+	}
+	fn compute_group_average_benchmark(&self, benchmark: &str, group: &str) -> BenchmarkResult {
+		average_benchmark(benchmark, self.benchmark_results.iter()
+			.filter( |&(queryname, _)| match self.query_groups.get(queryname) {
+				Some(g) => g.iter().any( |label| label == group ),
+				None => false,
+			})
+			.map( |(_, v)| v )
+		)
+	}
+}
+
+/// Averages a benchmark over the given per-query results.
+/// Returns BenchmarkResult::None if there are no queries to average over.
+fn average_benchmark<'a, I>(benchmark: &str, res: I) -> BenchmarkResult
+	where I: Iterator<Item = &'a HashMap<String, BenchmarkResult>> {
+	let mut acc = 0.0;
+	let mut c = 0;
+	for v in res {
+		match *v.get(benchmark).unwrap() {
+			BenchmarkResult::Scalar(f) => acc += f,
+			BenchmarkResult::Vector(_) => panic!("Don't know how to print a vector"),
+			BenchmarkResult::None => panic!("A query has no computed value"),
+		};
+		c += 1;
+	}
+	if c == 0 { return BenchmarkResult::None; }
+	BenchmarkResult::Scalar(acc / c as f32)
+}
+
+/// The key that a document is aggregated under; with `prefix_fields` = Some(n),
+/// only the first n '_'-separated fields of the document name are kept
+/// (e.g. "071" for "071_053_004" with n = 1).
+fn document_key(token: &RelevantToken, prefix_fields: Option<usize>) -> String {
+	let document = match token.get_document() {
+		Some(d) => d.clone(),
+		None => String::from("-"),
+	};
+	match prefix_fields {
+		Some(n) => document.split('_').take(n).collect::<Vec<&str>>().join("_"),
+		None => document,
+	}
+}
+
+impl DocumentBreakdown for RetrievalData {
+	/// Aggregates, over all evaluated queries, the relevant tokens, the hits and false positives
+	/// among the top k results, and the relevant tokens missing from the top k, per document.
+	fn document_breakdown(&self, k: usize, prefix_fields: Option<usize>) -> BTreeMap<String, DocumentCounts> {
+		let mut counts: BTreeMap<String, DocumentCounts> = BTreeMap::new();
+		let (relevant_tokens, result_tokens) = match (&self.relevant_tokens, &self.result_tokens) {
+			(Some(rel), Some(res)) => (rel, res),
+			_ => panic!("DocumentBreakdown::document_breakdown: Both a relevance and a result file must have been loaded"),
+		};
+		for (queryname, results) in result_tokens.iter().filter( |&(q, _)| self.benchmark_results.contains_key(q) ) {
+			let results = self.cut_off(results);
+			let topk = &results[0..k.min(results.len())];
+			// Relevant tokens are aggregated as they were when the results were scored
+			let loaded = relevant_tokens.get(queryname).unwrap();
+			let scored = self.scoring_relevants(loaded, results);
+			let relevants = scored.as_deref().unwrap_or(loaded);
+			for tok in topk {
+				let c = counts.entry(document_key(tok, prefix_fields)).or_default();
+				let hit = match scored {
+					Some(ref r) => r.iter().any( |pred| self.is_relevant(pred) && self.tokens_match(pred, tok) ),
+					None => self.is_this_a_hit(queryname, tok),
+				};
+				if hit { c.hits += 1; } else { c.false_positives += 1; }
+			}
+			for rel in relevants.iter().filter( |tok| self.is_relevant(tok) ) {
+				let c = counts.entry(document_key(rel, prefix_fields)).or_default();
+				c.relevant += 1;
+				if !topk.iter().any( |tok| self.tokens_match(rel, tok) ) { c.misses += 1; }
+			}
+		}
+		counts
+	}
+	fn print_document_breakdown(&self, k: usize, prefix_fields: Option<usize>) {
+		println!();
+		print!("DOCUMENT\t");
+		for b in &[String::from("relevant"), format!("hitsAt{}", k), format!("missesAt{}", k), format!("falsePosAt{}", k)] {
+			print!("{:width$}", b, width = 16)
+		}
+		println!();
+		println!("=======================================================================");
+		for (document, c) in self.document_breakdown(k, prefix_fields) {
+			println!("{}\t{:<16}{:<16}{:<16}{:<16}", document, c.relevant, c.hits, c.misses, c.false_positives);
+		}
+	}
+}
+
+impl MetricMAP for RetrievalData {
+	fn average_precision(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		let hitcount: Vec<f32> = self.cut_off(results).iter()
+			.map( |tok| if self.is_this_a_hit(queryname, tok) { 1.0 } else { 0.0 })
+			.collect();
+		let num_relevants = self.num_relevants(queryname) as f32;
+		let hitsum: Vec<f32> = hitcount.iter()
+			.scan(0.0, |state, x| {
+				*state += x;
+				Some(*state)
+			})
+			.enumerate()
+			.map( |(i, x)| x/(i as f32 + 1.0) )
+			.collect();
+		BenchmarkResult::Scalar(hitcount
+			.iter()
+			.zip(hitsum.iter())
+			.fold(0.0, |acc, (a,b)| acc + a*b ) / num_relevants
+		)
+	}
+}
+
+impl MetricPrecisionAtX for RetrievalData {
+	fn precision_at_x(&self, queryname: &str, results: &[RelevantToken], x: usize) -> BenchmarkResult {
+		let results = self.cut_off(results);
+		let topx = &results[0..x.min(results.len())];
+		let hitcount = topx.iter()
+			.map( |tok| if self.is_this_a_hit(queryname, tok) { 1.0 } else { 0.0 })
+			.fold(0.0, |acc, i| acc + i);
+		let mut denominator = x as f32;
+		let num_relevants = self.num_relevants(queryname) as f32;
+		if num_relevants < denominator { denominator = num_relevants }
+		BenchmarkResult::Scalar(hitcount / denominator)
+	}
+}
+
+/// Splits a ranked list into groups of consecutive tokens with equal scores.
+/// Returns (first rank, number of tokens, number of hits) for each group;
+/// tokens without a score always form a group of their own.
+fn tie_groups(hits: &[bool], results: &[RelevantToken]) -> Vec<(usize, usize, usize)> {
+	let mut groups: Vec<(usize, usize, usize)> = Vec::new();
+	for (i, tok) in results.iter().enumerate() {
+		let tied = i > 0 && tok.get_score().is_some() && tok.get_score() == results[i - 1].get_score();
+		let hit = if hits[i] { 1 } else { 0 };
+		match groups.last_mut() {
+			Some(g) if tied => { g.1 += 1; g.2 += hit; },
+			_ => groups.push((i, 1, hit)),
+		}
+	}
+	groups
+}
+
+impl MetricTieAware for RetrievalData {
+	fn expected_precision_at_x(&self, queryname: &str, results: &[RelevantToken], x: usize) -> BenchmarkResult {
+		let hits: Vec<bool> = results.iter().map( |tok| self.is_this_a_hit(queryname, tok) ).collect();
+		let limit = self.cut_off(results).len().min(x);
+		let mut hitcount = 0.0;
+		for (start, n, r) in tie_groups(&hits, results) {
+			if start >= limit { break; }
+			// Only part of the last group may fall within the top x, or above the cut-off
+			let inside = if start + n > limit { limit - start } else { n };
+			hitcount += inside as f32 * r as f32 / n as f32;
+		}
+		let mut denominator = x as f32;
+		let num_relevants = self.num_relevants(queryname) as f32;
+		if num_relevants < denominator { denominator = num_relevants }
+		BenchmarkResult::Scalar(hitcount / denominator)
+	}
+	fn expected_average_precision(&self, queryname: &str, results: &[RelevantToken]) -> BenchmarkResult {
+		let hits: Vec<bool> = results.iter().map( |tok| self.is_this_a_hit(queryname, tok) ).collect();
+		let limit = self.cut_off(results).len();
+		let num_relevants = self.num_relevants(queryname) as f32;
+		let mut hits_before = 0.0;
+		let mut acc = 0.0;
+		for (start, n, r) in tie_groups(&hits, results) {
+			if start >= limit { break; }
+			// Only the ranks of a group above the cut-off count
+			let inside = (limit - start).min(n);
+			let (n, r) = (n as f32, r as f32);
+			for j in 0..inside {
+				// Expected number of hits up to and including this rank, given that it is a hit
+				let others = if n > 1.0 { j as f32 * (r - 1.0) / (n - 1.0) } else { 0.0 };
+				acc += (r / n) * (hits_before + 1.0 + others) / (start + j + 1) as f32;
+			}
+			hits_before += r;
+		}
+		BenchmarkResult::Scalar(acc / num_relevants)
+	}
+}
+
+impl MetricNDCG for RetrievalData {
+	/// The grade of a token mapped to its gain, if a gain mapping is set; grades missing from the
+	/// mapping, and relevances, are their own gain.
+	fn gain(&self, token: &RelevantToken) -> f32 {
+		match (token.get_grade(), &self.gains) {
+			(Some(g), Some(gains)) => gains.get(&g).cloned().unwrap_or(g as f32),
+			_ => token.get_relevance(),
+		}
+	}
+	fn ndcg_at_x(&self, queryname: &str, results: &[RelevantToken], x: Option<usize>) -> BenchmarkResult {
+		let relevants = match self.relevant_tokens {
+			Some(ref i) => i.get(queryname).unwrap(),
+			None => panic!("Can't find relevant tokens list for query {}. Did you load a relevance file?", queryname),
+		};
+		let results = self.cut_off(results);
+		// Without a cut-off, the ideal ranking holds all relevant tokens, however short the result list
+		let x = x.unwrap_or(usize::MAX);
+		let discount = |i: usize| 1.0 / ((i + 2) as f32).log2();
+		let mut credited = vec![false; relevants.len()];
+		let mut dcg = 0.0;
+		for (i, tok) in results.iter().take(x).enumerate() {
+			let candidates: Vec<usize> = self.candidates(queryname, tok).unwrap_or_else( || (0..relevants.len()).collect() );
+			// The best matching relevant token that has not been credited yet
+			let best = candidates.into_iter()
+				.filter( |&j| !credited[j] && self.is_relevant(&relevants[j]) && self.tokens_match(&relevants[j], tok) )
+				.max_by( |&a, &b| self.gain(&relevants[a]).partial_cmp(&self.gain(&relevants[b])).unwrap_or(Ordering::Equal) );
+			if let Some(j) = best {
+				credited[j] = true;
+				dcg += self.gain(&relevants[j]) * discount(i);
+			}
+		}
+		let mut gains: Vec<f32> = relevants.iter()
+			.filter( |tok| self.is_relevant(tok) )
+			.map( |tok| self.gain(tok) )
+			.filter( |&g| g > 0.0 )
+			.collect();
+		gains.sort_by( |a, b| b.partial_cmp(a).unwrap_or(Ordering::Equal) );
+		let ideal: f32 = gains.iter().take(x).enumerate().map( |(i, g)| g * discount(i) ).sum();
+		BenchmarkResult::Scalar(if ideal > 0.0 { dcg / ideal } else { 0.0 })
+	}
+}
+
+#[cfg(test)]
+use parsers::{FileType, ParserXmlICFHR14, read_query_list, read_query_groups};
+#[cfg(test)]
+use parsers::{load_fixtures, load_fixtures_ties, load_fixtures_bentham};
+
+#[test]
+fn test_precision_at_x() {
+	let f = load_fixtures(true);
+	let res = f.benchmark_results;
+	let q0 = res.get("sb0000").unwrap();
+	let q1 = res.get("sb0001").unwrap();
+	match *q0.get("precAt10").unwrap() {
+		BenchmarkResult::Scalar(x) => assert!((x - 0.55555).abs() < 0.001),
+		_ => panic!("Expected a scalar"),
+	};
+	match *q1.get("precAt10").unwrap() {
+		BenchmarkResult::Scalar(x) => assert!((x - 0.44444).abs() < 0.001),
+		_ => panic!("Expected a scalar"),
+	};
+	match *q0.get("precAt5").unwrap() {
+		BenchmarkResult::Scalar(x) => assert_eq!(x, 1.0),
+		_ => panic!("Expected a scalar"),
+	};
+	match *q1.get("precAt5").unwrap() {
+		BenchmarkResult::Scalar(x) => assert_eq!(x, 0.8),
+		_ => panic!("Expected a scalar"),
+	};
+}
+
+#[test]
+fn test_tie_aware_metrics() {
+	let f = load_fixtures_ties();
+	let q0 = f.benchmark_results.get("sb0000").unwrap();
+	// Hits at ranks 1, 3, 6 and 10; ranks 1-2 and 4-6 are tied
+	let expected = [
+		("precAt5", 0.4), ("tiePrecAt5", 0.53333),
+		("precAt10", 0.44444), ("tiePrecAt10", 0.44444),
+		("ap", 0.28519), ("tieAp", 0.27037),
+	];
+	for &(b, should_compute) in expected.iter() {
+		match *q0.get(b).unwrap() {
+			BenchmarkResult::Scalar(x) => assert!((x - should_compute).abs() < 0.0001, "{} is {}", b, x),
+			_ => panic!("Expected a scalar"),
+		};
+	}
+}
+
+#[test]
+fn test_tie_aware_depth_cutoff() {
+	// The cut-off after rank 4 splits the tie group of ranks 4-6, whose only hit is at rank 6
+	let mut f = RetrievalData::new();
+	f.set_depth(4);
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsTiesSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	let expected = [("tiePrecAt5", (1.0 + 1.0 + 1.0 / 3.0) / 5.0), ("tieAp", (0.5 + 0.25 + 2.0 / 3.0 + 0.25) / 9.0)];
+	for order in 0..2 {
+		if order == 1 {
+			// The order within the tie group does not matter
+			f.result_tokens.as_mut().unwrap().get_mut("sb0000").unwrap().swap(3, 5);
+			f.evaluate();
+		}
+		for &(b, should_compute) in expected.iter() {
+			let x = f.benchmark_results["sb0000"][b].scalar().unwrap();
+			assert!((x - should_compute).abs() < 0.0001, "{} is {}", b, x);
+		}
+	}
+}
+
+#[test]
+fn test_tie_aware_metrics_without_ties() {
+	let f = load_fixtures(true);
+	for v in f.benchmark_results.values() {
+		for &(b, tie_b) in [("precAt5", "tiePrecAt5"), ("precAt10", "tiePrecAt10"), ("ap", "tieAp")].iter() {
+			match (v.get(b).unwrap(), v.get(tie_b).unwrap()) {
+				(&BenchmarkResult::Scalar(x), &BenchmarkResult::Scalar(y)) => assert!((x - y).abs() < 0.0001),
+				_ => panic!("Expected a scalar"),
+			};
+		}
+	}
+}
+
+#[test]
+fn test_depth_cutoff() {
+	let mut f = RetrievalData::new();
+	f.set_depth(5);
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	let q0 = f.benchmark_results.get("sb0000").unwrap();
+	// The top 5 results of sb0000 are all hits; everything below them is ignored
+	for &(b, should_compute) in [("precAt5", 1.0), ("precAt10", 0.55555), ("ap", 0.55555)].iter() {
+		match *q0.get(b).unwrap() {
+			BenchmarkResult::Scalar(x) => assert!((x - should_compute).abs() < 0.001, "{} is {}", b, x),
+			_ => panic!("Expected a scalar"),
+		};
+	}
+}
+
+#[test]
+fn test_min_relevance() {
+	let mut f = RetrievalData::new();
+	f.set_min_relevance(0.85);
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	assert_eq!(f.num_relevants("sb0000"), 6);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	let q0 = f.benchmark_results.get("sb0000").unwrap();
+	match *q0.get("precAt10").unwrap() {
+		BenchmarkResult::Scalar(x) => assert!((x - 0.83333).abs() < 0.001),
+		_ => panic!("Expected a scalar"),
+	};
+}
+
+#[test]
+fn test_selected_queries() {
+	let mut f = RetrievalData::new();
+	f.set_selected_queries(read_query_list("fixtures/QueryListSample.txt"));
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	assert_eq!(f.benchmark_results.len(), 1);
+	assert!(f.benchmark_results.contains_key("sb0001"));
+	match f.compute_average_benchmark("precAt5") {
+		BenchmarkResult::Scalar(x) => assert_eq!(x, 0.8),
+		_ => panic!("Expected a scalar"),
+	};
+}
+
+#[test]
+fn test_query_groups() {
+	let mut f = RetrievalData::new();
+	f.set_query_groups(read_query_groups("fixtures/QueryGroupsSample.txt"));
+	assert_eq!(f.group_labels(), vec!["all words", "frequent", "rare"]);
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	let expected = [("frequent", 1.0), ("rare", 0.8), ("all words", 0.9)];
+	for &(group, should_compute) in expected.iter() {
+		match f.compute_group_average_benchmark("precAt5", group) {
+			BenchmarkResult::Scalar(x) => assert!((x - should_compute).abs() < 0.0001),
+			_ => panic!("Expected a scalar"),
+		};
+	}
+	match f.compute_group_average_benchmark("precAt5", "nonexistent") {
+		BenchmarkResult::None => {},
+		_ => panic!("Expected no value"),
+	};
+}
+
+#[test]
+fn test_document_breakdown() {
+	let f = load_fixtures(true);
+	let counts = f.document_breakdown(5, None);
+	assert_eq!(counts.values().fold(0, |acc, c| acc + c.relevant), 18);
+	assert_eq!(counts.values().fold(0, |acc, c| acc + c.hits), 9);
+	assert_eq!(counts.values().fold(0, |acc, c| acc + c.false_positives), 1);
+	assert_eq!(counts.values().fold(0, |acc, c| acc + c.misses), 9);
+	let c = counts.get("027_029_001").unwrap();
+	assert_eq!((c.relevant, c.hits, c.misses), (10, 8, 2));
+	let counts = f.document_breakdown(5, Some(1));
+	assert_eq!(counts.get("027").unwrap().relevant, 10);
+	assert_eq!(counts.get("071").unwrap().relevant, 6);
+}
+
+#[test]
+fn test_graded_relevance() {
+	use parsers::ParserTrecEval;
+	let load = |configure: &dyn Fn(&mut RetrievalData)| {
+		let mut f = RetrievalData::new();
+		configure(&mut f);
+		f.set_relevants_filename(String::from("fixtures/GradedQrelsSample.txt"));
+		f.parse_trec_file(FileType::RelevantTokensFile);
+		f.set_results_filename(String::from("fixtures/GradedResultsSample.txt"));
+		f.parse_trec_file(FileType::ResultsFile);
+		f
+	};
+	let benchmark = |f: &RetrievalData, b: &str| f.benchmark_results["g0"][b].scalar().unwrap();
+	// Grades are kept, judged non-relevant tokens included; grades of 0 or less are non-relevant
+	let f = load(&|_| {});
+	let grades: Vec<Option<i32>> = f.relevant_tokens().unwrap()["g0"].iter().map( |t| t.get_grade() ).collect();
+	assert_eq!(grades, vec![Some(3), Some(2), Some(0), Some(1), Some(-1)]);
+	assert_eq!(f.num_relevants("g0"), 3);
+	// Hits at ranks 2 (grade 3) and 4 (grade 1); the gains are the grades
+	assert!((benchmark(&f, "ap") - 0.33333).abs() < 0.001);
+	let ideal = 3.0 + 2.0 / 3f32.log2() + 0.5;
+	assert!((benchmark(&f, "ndcg") - (3.0 / 3f32.log2() + 1.0 / 5f32.log2()) / ideal).abs() < 0.001);
+	let gains: HashMap<i32, f32> = vec![(0, 0.0), (1, 1.0), (2, 3.0), (3, 7.0)].into_iter().collect();
+	let f = load(&|f| f.set_gains(gains.clone()));
+	let ideal = 7.0 + 3.0 / 3f32.log2() + 0.5;
+	assert!((benchmark(&f, "ndcg") - (7.0 / 3f32.log2() + 1.0 / 5f32.log2()) / ideal).abs() < 0.001);
+	assert_eq!(benchmark(&f, "ndcgAt10"), benchmark(&f, "ndcg"));
+	// Grades missing from the mapping are their own gain
+	let f = load(&|f| f.set_gains(vec![(2, 3.0)].into_iter().collect()));
+	let ideal = 3.0 + 3.0 / 3f32.log2() + 0.5;
+	assert!((benchmark(&f, "ndcg") - (3.0 / 3f32.log2() + 1.0 / 5f32.log2()) / ideal).abs() < 0.001);
+	// Relevance values of ICFHR'14 files are not grades
+	let icfhr = load_fixtures(false);
+	assert!(icfhr.relevant_tokens().unwrap().values().flatten().all( |t| t.get_grade().is_none() && t.relevance.is_some() ));
+	// The ideal ranking is not cut off at the length of the result list
+	let mut short = RetrievalData::new();
+	short.set_relevants_filename(String::from("fixtures/GradedQrelsSample.txt"));
+	short.parse_trec_file(FileType::RelevantTokensFile);
+	let best = short.relevant_tokens().unwrap()["g0"][0].clone();
+	short.add_result_token("g0", best);
+	short.evaluate();
+	assert!((benchmark(&short, "ndcg") - 3.0 / (3.0 + 2.0 / 3f32.log2() + 0.5)).abs() < 0.001);
+	// A threshold on grades gives binary relevance
+	let f = load(&|f| f.set_min_relevance(2.0));
+	assert_eq!(f.num_relevants("g0"), 2);
+	assert!((benchmark(&f, "ap") - 0.25).abs() < 0.001);
+}
+
+#[test]
+fn test_polygon_matching() {
+	use rectangles::Rectangle;
+	let mut f = RetrievalData::new();
+	let rect = Rectangle::from_min_size(159, 1775, 184, 89).unwrap();
+	let mut relevant = RelevantToken::new();
+	relevant.set_tokenid(TokenId::BoundingBox(rect)).set_document(String::from("027_029_001"));
+	let same_region = f.parse_tokenstring_fast(r#"<word document="027_029_001" points="159,1775 343,1775 343,1864 159,1864" />"#);
+	let skewed = f.parse_tokenstring_fast(r#"<word document="027_029_001" points="163,1770 345,1781 341,1866 158,1858" Score="0.9" />"#);
+	let elsewhere = f.parse_tokenstring_fast(r#"<word document="071_053_004" points="159,1775 343,1775 343,1864 159,1864" />"#);
+	assert_eq!(skewed.get_score(), Some(0.9));
+	// Without a threshold, only identical regions match
+	assert!(f.tokens_match(&relevant, &same_region) && f.tokens_match(&same_region, &relevant));
+	assert!(!f.tokens_match(&relevant, &skewed));
+	assert!(!f.tokens_match(&relevant, &elsewhere));
+	f.set_overlap_threshold(0.5);
+	assert!(f.tokens_match(&relevant, &skewed) && f.tokens_match(&skewed, &same_region));
+	assert!(!f.tokens_match(&relevant, &elsewhere));
+	// Boxes are compared by overlap too, once a threshold is set
+	let shifted = Rectangle::from_min_size(200, 1775, 184, 89).unwrap();
+	let mut shifted_box = RelevantToken::new();
+	shifted_box.set_tokenid(TokenId::BoundingBox(shifted)).set_document(String::from("027_029_001"));
+	assert!(f.tokens_match(&relevant, &shifted_box));
+	f.set_overlap_threshold(0.9);
+	assert!(!f.tokens_match(&relevant, &shifted_box));
+}
+
+#[test]
+fn test_spatial_index_matching() {
+	let mut f = load_fixtures(true);
+	f.set_overlap_threshold(0.5);
+	let results = f.result_tokens().unwrap().clone();
+	let mut hits = 0;
+	for (queryname, tokens) in &results {
+		// Boxes shifted by a few pixels, some of which no longer overlap enough
+		let shifted: Vec<RelevantToken> = tokens.iter().enumerate().map( |(i, tok)| {
+			let mut tok = tok.clone();
+			if let TokenId::BoundingBox(rect) = tok.id {
+				tok.set_tokenid(TokenId::BoundingBox(rect.translate(8 * (i % 20) as i64, 3).unwrap()));
+			}
+			tok
+		}).collect();
+		let linear: Vec<bool> = shifted.iter().map( |tok| f.is_this_a_hit(queryname, tok) ).collect();
+		let index = SpatialIndex::new(&f.relevant_tokens().unwrap()[queryname]);
+		f.spatial_index = Some((queryname.clone(), index));
+		let indexed: Vec<bool> = shifted.iter().map( |tok| f.is_this_a_hit(queryname, tok) ).collect();
+		f.spatial_index = None;
+		assert_eq!(linear, indexed);
+		hits += indexed.iter().filter( |&&h| h ).count();
+	}
+	assert!(hits > 0);
+}
+
+#[test]
+fn test_line_matching() {
+	use parsers::ParserXmlICFHR14;
+	use parsers::FileType;
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/LineRelevanceSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/LineResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	// Retrieved lines l11 of 027_029_001 and l07 of 071_053_004 contain a relevant word
+	let hits: Vec<bool> = f.result_tokens().unwrap()["ln0000"].iter().map( |tok| f.is_this_a_hit("ln0000", tok) ).collect();
+	assert_eq!(hits, vec![true, false, true, false]);
+	let ap = f.benchmark_results()["ln0000"]["ap"].scalar().unwrap();
+	assert!((ap - (1.0 + 2.0 / 3.0) / 3.0).abs() < 1e-6);
+	// Spans match identical spans, or overlapping spans of the same line once a threshold is set
+	let span = f.parse_tokenstring_fast(r#"<word document="027_029_001" line="l03" start="3" end="5" Score="0.5" />"#);
+	let word = f.parse_tokenstring_fast(r#"<word document="027_029_001" line="l03" start="4" end="4" />"#);
+	let line = f.parse_tokenstring_fast(r#"<word document="027_029_001" line="l03" Score="0.5" />"#);
+	assert_eq!(span.get_score(), Some(0.5));
+	assert!(f.tokens_match(&word, &line) && f.tokens_match(&line, &span));
+	assert!(!f.tokens_match(&word, &span));
+	f.set_overlap_threshold(0.3);
+	assert!(f.tokens_match(&word, &span));
+	f.set_overlap_threshold(0.5);
+	assert!(!f.tokens_match(&word, &span));
+
+	// A retrieved line holding several relevant words counts once
+	let mut f = RetrievalData::new();
+	for tokstr in [r#"document="027_029_001" line="l03" start="1" end="1""#, r#"document="027_029_001" line="l03" start="4" end="4""#,
+		r#"document="027_029_001" line="l05" start="2" end="3""#].iter() {
+		let tok = f.parse_tokenstring_fast(tokstr);
+		f.add_relevant_token("q", tok);
+	}
+	let tok = f.parse_tokenstring_fast(r#"document="027_029_001" line="l03""#);
+	f.add_result_token("q", tok);
+	// The spans are only collapsed for scoring, so evaluating again gives the same numbers
+	for _ in 0..2 {
+		f.evaluate();
+		assert_eq!(f.num_relevants("q"), 3);
+		for &(b, expected) in [("ap", 0.5), ("precAt5", 0.5), ("ndcg", 1.0 / (1.0 + 1.0 / 3f32.log2()))].iter() {
+			assert!((f.benchmark_results()["q"][b].scalar().unwrap() - expected).abs() < 1e-6, "{}", b);
+		}
+	}
+}
+
+#[test]
+fn test_document_level_evaluation() {
+	use parsers::{FileType, ParserXmlICFHR14};
+	use std::collections::HashSet;
+	use aggregation::DocumentRanking;
+	let mut f = load_fixtures(false);
+	f.set_document_level(DocumentRanking::MaxScore);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	// Word-level judgements are kept as loaded; documents are only aggregated for scoring
+	assert_eq!(f.num_relevants("sb0000"), load_fixtures(false).num_relevants("sb0000"));
+	assert!(f.relevant_tokens().unwrap()["sb0000"].iter().all( |tok| matches!(tok.id, TokenId::BoundingBox(_)) ));
+	let averages = |f: &RetrievalData| {
+		let mut benchmarks = f.computed_benchmarks();
+		benchmarks.sort();
+		benchmarks.iter().map( |b| f.compute_average_benchmark(b).scalar().unwrap() ).collect::<Vec<f32>>()
+	};
+	let first = averages(&f);
+	f.evaluate();
+	assert_eq!(averages(&f), first);
+	// The document breakdown counts documents, as they were scored
+	let breakdown = f.document_breakdown(10, None);
+	assert!(breakdown.values().any( |c| c.hits > 0 ));
+	let documents: usize = f.relevant_tokens().unwrap().values().map( |tokens| tokens.iter()
+		.filter_map( |tok| tok.get_document() ).collect::<HashSet<_>>().len() ).sum();
+	assert_eq!(breakdown.values().map( |c| c.relevant ).sum::<usize>(), documents);
+}
+
+#[test]
+fn test_bentham_precision() {
+	let f = load_fixtures_bentham(true);
+	let benchmarks = ["precAt10", "precAt5", "ap"];
+	// These results coincide with the ones reported at the ICFHR'14 competition, [Pratikakis et al. 2014]
+	let should_compute = [0.60267615, 0.73812526, 0.5240239];
+	for (i, &b) in benchmarks.iter().enumerate() {
+		if let BenchmarkResult::Scalar(score) = f.compute_average_benchmark(b) {
+			println!("{} should have been {}, computed is {}", b, should_compute[i], score);
+			assert!((should_compute[i] - score).abs() < 0.001);
+		}
+	}
+}
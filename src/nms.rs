@@ -0,0 +1,104 @@
+//! Non-maximum suppression of result lists, for segmentation-free runs that return many
+//! overlapping boxes around the same word.
+//!
+//! Results are visited by decreasing score (in rank order if some results have no score), and a
+//! region is removed if it overlaps a region kept before it, on the same document, with an
+//! intersection over union of at least the threshold. The remaining results keep their rank order.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use metrics::region;
+use spatial::SpatialIndex;
+use tokens::{RelevantToken, TokenId};
+
+#[derive(Debug)]
+pub struct NonMaximumSuppression {
+	pub threshold: f32,
+	/// For each query, the number of results before suppression and the number of results removed.
+	pub counts: HashMap<String, (usize, usize)>,
+}
+
+/// The intersection over union of the regions of two tokens; 0 for tokens without a region.
+fn iou(a: &RelevantToken, b: &RelevantToken) -> f64 {
+	match (&a.id, &b.id) {
+		(TokenId::BoundingBox(a_box), TokenId::BoundingBox(b_box)) => a_box.iou(b_box),
+		_ => match (region(a), region(b)) {
+			(Some(a_region), Some(b_region)) => a_region.iou(&b_region),
+			_ => 0.0,
+		},
+	}
+}
+
+impl NonMaximumSuppression {
+	/// Panics unless `threshold` is in (0, 1].
+	pub fn new(threshold: f32) -> NonMaximumSuppression {
+		if threshold <= 0.0 || threshold > 1.0 { panic!("Invalid value for the suppression threshold") }
+		NonMaximumSuppression { threshold, counts: HashMap::new() }
+	}
+	/// Removes the suppressed results of a query from its result list.
+	pub fn apply(&mut self, queryname: &str, results: &mut Vec<RelevantToken>) {
+		let mut order: Vec<usize> = (0..results.len()).collect();
+		if results.iter().all( |tok| tok.get_score().is_some() ) {
+			order.sort_by( |&i, &j| results[j].get_score().partial_cmp(&results[i].get_score()).unwrap_or(Ordering::Equal) );
+		}
+		let index = SpatialIndex::new(results);
+		let mut kept = vec![false; results.len()];
+		for i in order {
+			kept[i] = match index.candidates(&results[i]) {
+				Some(c) => !c.iter().any( |&j| kept[j] && iou(&results[i], &results[j]) >= self.threshold as f64 - 1e-9 ),
+				None => true,
+			};
+		}
+		let before = results.len();
+		let mut flags = kept.into_iter();
+		results.retain( |_| flags.next().unwrap() );
+		let counts = self.counts.entry(String::from(queryname)).or_insert((before, 0));
+		counts.1 += before - results.len();
+	}
+	/// The number of results before suppression and the number of results removed, over all queries.
+	pub fn totals(&self) -> (usize, usize) {
+		self.counts.values().fold((0, 0), |acc, c| (acc.0 + c.0, acc.1 + c.1))
+	}
+}
+
+#[test]
+fn test_non_maximum_suppression() {
+	use rectangles::Rectangle;
+	let token = |document: &str, x: u32, score: Option<f32>| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::BoundingBox(Rectangle::from_min_size(x, 100, 100, 50).unwrap()))
+			.set_document(String::from(document));
+		if let Some(s) = score { tok.set_score(s); }
+		tok
+	};
+	let xs = |results: &[RelevantToken]| -> Vec<u32> {
+		results.iter().map( |t| match t.id { TokenId::BoundingBox(r) => r.min().get_x(), _ => panic!() } ).collect()
+	};
+	// Boxes shifted by 10 pixels overlap with an IoU of 0.82, by 50 pixels of 0.33
+	let mut results = vec![
+		token("a", 10, Some(0.8)),
+		token("a", 0, Some(0.9)),
+		token("b", 0, Some(0.7)),
+		token("a", 50, Some(0.6)),
+		token("a", 500, Some(0.5)),
+	];
+	let mut id = RelevantToken::new();
+	id.set_tokenid(TokenId::NumericId(3)).set_score(0.4);
+	results.push(id);
+	let mut nms = NonMaximumSuppression::new(0.5);
+	nms.apply("q", &mut results);
+	// The best scoring box is kept, on each document; the order of the results is unchanged
+	assert_eq!(xs(&results[..4]), vec![0, 0, 50, 500]);
+	assert_eq!(results.len(), 5);
+	assert_eq!(nms.totals(), (6, 1));
+	// Suppression is idempotent
+	nms.apply("q", &mut results);
+	assert_eq!((results.len(), nms.totals()), (5, (6, 1)));
+
+	// Without scores, results are visited in rank order
+	let mut results = vec![token("a", 10, None), token("a", 0, Some(0.9)), token("a", 50, None)];
+	let mut nms = NonMaximumSuppression::new(0.3);
+	nms.apply("q", &mut results);
+	assert_eq!(xs(&results), vec![10]);
+	assert_eq!(nms.totals(), (3, 2));
+}
@@ -0,0 +1,793 @@
+use std::io;
+use std::io::BufRead;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use regex::Regex;
+
+use rectangles::Rectangle;
+use polygons::Polygon;
+use tokens::RelevantToken;
+use tokens::TokenId;
+use input::open_input;
+use cache::{is_index_file, read_index};
+use metrics::Benchmark;
+use retrieval::RetrievalData;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileType {
+	ResultsFile,
+	RelevantTokensFile,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+	XmlICFHR14,
+	TrecEval,
+}
+
+impl FileFormat {
+	pub fn from_name(name: &str) -> Option<FileFormat> {
+		match name {
+			"icfhr" | "icfhr14" | "xml" => Some(FileFormat::XmlICFHR14),
+			"trec" | "trec_eval" => Some(FileFormat::TrecEval),
+			_ => None,
+		}
+	}
+}
+
+/// Guesses the format and the type of a relevance or result file from its first lines.
+/// Index files are reported as relevance files in the ICFHR'14 format, which `parse_file` loads.
+pub fn detect_format(filename: &str) -> io::Result<(FileFormat, FileType)> {
+	if is_index_file(filename) { return Ok((FileFormat::XmlICFHR14, FileType::RelevantTokensFile)); }
+	if filename == "-" {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "The format of the standard input can't be detected"));
+	}
+	for buffer in open_input(filename)?.lines() {
+		let current_line = buffer?;
+		let line = current_line.trim_start_matches('\u{feff}').trim();
+		if line.is_empty() { continue; }
+		if line.starts_with('<') {
+			if line.contains("<GTRel ") { return Ok((FileFormat::XmlICFHR14, FileType::RelevantTokensFile)); }
+			if line.contains("<Rel ") { return Ok((FileFormat::XmlICFHR14, FileType::ResultsFile)); }
+			continue;
+		}
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		let is_run = fields.len() >= 6 && fields[3].parse::<i64>().is_ok() && fields[4].parse::<f64>().is_ok();
+		return Ok((FileFormat::TrecEval, if is_run { FileType::ResultsFile } else { FileType::RelevantTokensFile }));
+	}
+	Err(io::Error::new(io::ErrorKind::InvalidData, format!("Can't detect the format of {}", filename)))
+}
+
+pub fn xml_unescape(s: &str) -> String {
+	s.replace("&quot;", "\"").replace("&lt;", "<").replace("&gt;", ">").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+/// Parses a list of at least three polygon vertices, `x1,y1 x2,y2 ...`, `x1,y1,x2,y2,...`, or anything in between.
+pub fn parse_points(s: &str) -> Option<Polygon> {
+	let values: Vec<u32> = match s.split( |c: char| c == ',' || c.is_whitespace() ).filter( |v| !v.is_empty() ).map( |v| v.parse() ).collect() {
+		Ok(v) => v,
+		Err(_) => return None,
+	};
+	if values.len() < 6 || !values.len().is_multiple_of(2) { return None; }
+	Some(Polygon::new(values.chunks(2).map( |p| (p[0], p[1]) ).collect()))
+}
+
+/// A line, or a span of it if `range` (or else a `:start-end` suffix of `id`) gives valid offsets.
+pub(crate) fn parse_line(id: &str, range: Option<(&str, &str)>) -> TokenId {
+	let (line, range) = match range {
+		Some(r) => (id, Some(r)),
+		None => match id.rsplit_once(':') {
+			Some((line, r)) if !line.is_empty() => (line, r.split_once('-')),
+			_ => (id, None),
+		},
+	};
+	match range.map( |(s, e)| (s.parse::<u32>(), e.parse::<u32>()) ) {
+		Some((Ok(s), Ok(e))) if s <= e => TokenId::Span(String::from(line), s, e),
+		_ => TokenId::Line(String::from(id)),
+	}
+}
+
+/// Decodes a trec docno; docnos of the form `document_x_y_w_h`, `document_poly_x1,y1,x2,y2,...`,
+/// `document_line_id` or `document_line_id:start-end`, as written by `encode_docno`, become bounding
+/// boxes, polygons, lines or spans, anything else a string id.
+pub fn decode_docno(docno: &str) -> RelevantToken {
+	let mut tok = RelevantToken::new();
+	let (document, points) = match docno.rfind("poly_") {
+		Some(0) => (None, &docno[5..]),
+		Some(i) if docno[..i].ends_with('_') => (Some(&docno[..i - 1]), &docno[i + 5..]),
+		_ => (None, ""),
+	};
+	if let Some(poly) = parse_points(points) {
+		tok.set_tokenid(TokenId::Polygon(poly));
+		if let Some(d) = document { tok.set_document(String::from(d)); }
+		return tok;
+	}
+	let line = match docno.find("line_") {
+		Some(0) => Some((None, &docno[5..])),
+		_ => docno.find("_line_").map( |i| (Some(&docno[..i]), &docno[i + 6..]) ),
+	};
+	if let Some((document, id)) = line.filter( |&(_, id)| !id.is_empty() ) {
+		tok.set_tokenid(parse_line(id, None));
+		if let Some(d) = document { tok.set_document(String::from(d)); }
+		return tok;
+	}
+	let fields: Vec<&str> = docno.rsplitn(5, '_').collect();
+	let coords: Vec<u32> = fields.iter().take(4).filter_map( |f| f.parse().ok() ).collect();
+	let rect = match coords[..] {
+		[h, w, y, x] => Rectangle::from_min_size(x, y, w, h).ok(),
+		_ => None,
+	};
+	match rect {
+		Some(rect) => {
+			tok.set_tokenid(TokenId::BoundingBox(rect));
+			if fields.len() == 5 { tok.set_document(String::from(fields[4])); }
+		},
+		None => { tok.set_tokenid(TokenId::StringId(String::from(docno))); },
+	}
+	tok
+}
+
+/// Splits off the first `n` whitespace-separated fields of a line, and returns them with the rest of the line.
+fn split_fields(line: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+	let mut fields = Vec::with_capacity(n);
+	let mut rest = line.trim_start();
+	while fields.len() < n {
+		if rest.is_empty() { return None; }
+		let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+		fields.push(&rest[..end]);
+		rest = rest[end..].trim_start();
+	}
+	Some((fields, rest.trim_end()))
+}
+
+pub trait ParserTrecEval : Benchmark {
+	/// Parses a line of a trec qrels (`qid 0 docno rel`) or run (`qid 0 docno rank sim run_id`) file,
+	/// optionally followed by a transcription. Relevance files yield None as token for non-relevant docnos.
+	fn parse_trec_line(&self, line: &str, ft: FileType) -> (String, Option<RelevantToken>);
+	fn parse_trec_file(&mut self, ft: FileType);
+}
+
+pub trait ParserXmlICFHR14 : Benchmark {
+	fn parse_tokenstring_fast(&self, tokstr: &str) -> RelevantToken;
+	fn parse_tokenstring(&self, tokstr: &str) -> RelevantToken;
+	fn parse_file(&mut self, ft: FileType);
+	fn parse_query_instances_file(&mut self);
+}
+
+impl ParserXmlICFHR14 for RetrievalData {
+	fn parse_tokenstring_fast(&self, tokstr: &str) -> RelevantToken {
+		let mut next_token = 0;
+		let mut invalue = 0;
+		let mut endpoints = [[0usize; 2]; 8];
+
+		let mut tok = RelevantToken::new();
+		for (n, c) in tokstr.char_indices() {
+			if c == '"' {
+				endpoints[next_token][invalue] = n;
+				invalue = 1 - invalue;
+				if invalue == 0 { next_token += 1; }
+			}
+			if next_token == endpoints.len() { break; }
+		}
+		let field_name = |field: &[usize; 2]| tokstr[..field[0]].trim_end_matches('=').rsplit(char::is_whitespace).next().unwrap();
+		let document = xml_unescape(&tokstr[endpoints[0][0]+1..endpoints[0][1]]);
+		let value = |field: &[usize; 2]| &tokstr[field[0]+1..field[1]];
+		// Regions are either a bounding box or a polygon, given as `points="x1,y1 x2,y2 ..."`;
+		// text lines are given as `line="id"`, optionally followed by the `start` and `end` of a span
+		let optional_fields = match field_name(&endpoints[1]) {
+			"points" => {
+				let poly = parse_points(value(&endpoints[1])).expect("Invalid polygon points");
+				tok.set_tokenid(TokenId::Polygon(poly));
+				2
+			},
+			"line" if field_name(&endpoints[2]) == "start" && field_name(&endpoints[3]) == "end" => {
+				match parse_line(&xml_unescape(value(&endpoints[1])), Some((value(&endpoints[2]), value(&endpoints[3])))) {
+					span @ TokenId::Span(..) => tok.set_tokenid(span),
+					_ => panic!("Invalid span offsets"),
+				};
+				4
+			},
+			"line" => {
+				tok.set_tokenid(TokenId::Line(xml_unescape(value(&endpoints[1]))));
+				2
+			},
+			_ => {
+				let x = value(&endpoints[1]).parse().unwrap();
+				let y = value(&endpoints[2]).parse().unwrap();
+				let w = value(&endpoints[3]).parse().unwrap();
+				let h = value(&endpoints[4]).parse().unwrap();
+				let rect = Rectangle::from_min_size(x, y, w, h).unwrap_or_else( |e| panic!("Invalid bounding box: {}", e) );
+				tok.set_tokenid(TokenId::BoundingBox(rect));
+				5
+			},
+		};
+		// Optional fields (Text, Relevance, Score) may follow in any order
+		for field in endpoints[optional_fields..].iter().take_while(|field| field[1] > 0) {
+			let value = value(field);
+			match field_name(field) {
+				// Also accept the 'elevance' typo found in some published relevance files
+				"Relevance" | "elevance" => { tok.set_relevance(value.parse().unwrap()); },
+				"Grade" 				=> { tok.set_grade(value.parse().unwrap_or_else( |_| panic!("Invalid relevance grade: {}", value) )); },
+				"Text" 					=> { tok.set_transcription(xml_unescape(value)); },
+				"Score" 				=> { tok.set_score(value.parse().unwrap()); },
+				_ 						=> panic!("Unidentified token field"),
+			}
+		}
+		tok.set_document(document);
+		tok
+	}
+	fn parse_tokenstring(&self, tokstr: &str) -> RelevantToken {
+		let mut token_traits_hash = HashMap::new();
+		token_traits_hash.insert("bbox", 		r#"x="(\d+)" y="(\d+)" width="(\d+)" height="(\d+)""#);
+		token_traits_hash.insert("id", 	 		r#"id="(\d+)"#);
+		token_traits_hash.insert("docname", 	r#"word document="(.*?)""#);
+		token_traits_hash.insert("relevance",	r#"Relevance="([\d\.]+)""#);
+		token_traits_hash.insert("score",		r#"Score="([-+\d\.eE]+)""#);
+		let token_traits_hash = token_traits_hash;
+
+		let mut tok = RelevantToken::new();
+		for (&k, &val) in &token_traits_hash {
+			let re = Regex::new(val).unwrap();
+			for l in re.captures_iter(tokstr) {
+				match k {
+					"bbox"		=> {
+						let x: u32 = l.at(1).unwrap().parse().expect("oops");
+						let y: u32 = l.at(2).unwrap().parse().expect("oops");
+						let w: u32 = l.at(3).unwrap().parse().expect("oops");
+						let h: u32 = l.at(4).unwrap().parse().expect("oops");
+						let rect = Rectangle::from_min_size(x, y, w, h).expect("oops");
+						tok.set_tokenid(TokenId::BoundingBox(rect));
+					},
+					"id"			 => panic!("This isn't implemented yet"),
+					"docname"		 => { tok.set_document(xml_unescape(l.at(1).unwrap())); },
+					"relevance" 	 => {
+						let relv: f32 = l.at(1).unwrap().parse().expect("oops");
+						tok.set_relevance(relv);
+					},
+					"score" 		 => {
+						let score: f32 = l.at(1).unwrap().parse().expect("oops");
+						tok.set_score(score);
+					},
+					_				 => panic!("Don't know how to handle this token trait."),
+				}
+			}
+		}
+		tok
+	}
+
+	fn parse_file(&mut self, ft: FileType) {
+		enum ParsingState {
+			WaitingNextQuery,
+			InQueryBlock(String),
+		}
+		let (filename, re_querystarts, re_queryends) = match ft {
+			FileType::RelevantTokensFile => (
+				self.relevant_tokens_filename.clone(),
+				Regex::new(r#"<GTRel queryid="([^"]*)"([^>]*)>"#).unwrap(),
+				Regex::new(r#"</GTRel>"#).unwrap(),
+
+				),
+			FileType::ResultsFile => (
+				self.result_tokens_filename.clone(),
+				Regex::new(r#"<Rel queryid="([^"]*)"([^>]*)>"#).unwrap(),
+				Regex::new(r#"</Rel>"#).unwrap(),
+			),
+		};
+		let filename = match filename {
+			Some(f) => f,
+			None => panic!("oops"),
+		};
+		if let FileType::RelevantTokensFile = ft {
+			if is_index_file(&filename) {
+				read_index(self, &filename, self.allow_missing_index_source).expect("Index file could not be read.");
+				return;
+			}
+		}
+		let f = open_input(&filename).expect("Input xml could not be read.");
+
+		let mut relevant_tokens: Vec<RelevantToken> = Vec::new();
+		let mut res = HashMap::new();
+
+		let mut state = ParsingState::WaitingNextQuery;
+		let mut re_state_must_change = re_querystarts.clone();
+
+		for buffer in f.lines() {
+			let current_line = buffer.unwrap();
+			match re_state_must_change.captures_iter(&current_line[..]).next() {
+				Some(captured_queryname) => match state {
+					ParsingState::WaitingNextQuery => {
+						let query_name = String::from(captured_queryname.at(1).unwrap());
+						// The query instance itself may be given as attributes of the query element
+						let attributes = captured_queryname.at(2).unwrap_or("");
+						if attributes.contains("document=") {
+							let query_token = self.parse_tokenstring_fast(attributes);
+							self.query_instances.insert(query_name.clone(), query_token);
+						}
+						state = ParsingState::InQueryBlock(query_name);
+						re_state_must_change = re_queryends.clone();
+					},
+					ParsingState::InQueryBlock(query_name) => {
+						self.finish_query_block(ft, &query_name, &mut relevant_tokens);
+						res.insert(query_name.clone(), relevant_tokens);
+						relevant_tokens = Vec::new();
+						state = ParsingState::WaitingNextQuery;
+						re_state_must_change = re_querystarts.clone();
+					},
+				},
+				None => match state {
+					ParsingState::WaitingNextQuery => {},
+					ParsingState::InQueryBlock(ref query_name) => {
+						let tok = self.parse_tokenstring_fast(&current_line);
+						if let FileType::ResultsFile = ft {
+							if self.streaming && !self.must_keep_result(query_name, &relevant_tokens, &tok) { continue; }
+						}
+						relevant_tokens.push(tok);
+					},
+				},
+			};
+		}
+		match ft {
+			FileType::RelevantTokensFile => self.relevant_tokens = Some(res),
+			FileType::ResultsFile => self.result_tokens = Some(res),
+		};
+	}
+	fn parse_query_instances_file(&mut self) {
+		let re_query = Regex::new(r#"<Query queryid="([^"]*)"([^>]*)>"#).unwrap();
+		let filename = match self.query_instances_filename {
+			Some(ref f) => f.clone(),
+			None => panic!("No query instances file has been set"),
+		};
+		let f = open_input(&filename).expect("Input xml could not be read.");
+		for buffer in f.lines() {
+			let current_line = buffer.unwrap();
+			if let Some(captured) = re_query.captures_iter(&current_line[..]).next() {
+				let query_token = self.parse_tokenstring_fast(captured.at(2).unwrap());
+				self.query_instances.insert(String::from(captured.at(1).unwrap()), query_token);
+			}
+		}
+	}
+}
+
+impl ParserTrecEval for RetrievalData {
+	fn parse_trec_line(&self, line: &str, ft: FileType) -> (String, Option<RelevantToken>) {
+		let n = match ft {
+			FileType::RelevantTokensFile => 4,
+			FileType::ResultsFile => 6,
+		};
+		let (fields, text) = split_fields(line, n).unwrap_or_else( || panic!("Malformed trec line: {}", line) );
+		let mut tok = decode_docno(fields[2]);
+		if !text.is_empty() { tok.set_transcription(String::from(text)); }
+		match ft {
+			FileType::RelevantTokensFile => {
+				// Integers are relevance grades, as in graded qrels (e.g. 0 to 3, or negative for spam), and
+				// judged non-relevant tokens are kept; other values are relevances in (0, 1]
+				match fields[3].parse::<i32>() {
+					Ok(g) => { tok.set_grade(g); },
+					Err(_) => {
+						let relevance: f32 = fields[3].parse().expect("Invalid relevance value");
+						if relevance <= 0.0 { return (String::from(fields[0]), None); }
+						tok.set_relevance(relevance);
+					},
+				}
+			},
+			FileType::ResultsFile => { tok.set_score(fields[4].parse().expect("Invalid similarity value")); },
+		}
+		(String::from(fields[0]), Some(tok))
+	}
+	/// Result lists are ordered by decreasing similarity, keeping the file order for equal similarities.
+	fn parse_trec_file(&mut self, ft: FileType) {
+		let filename = match ft {
+			FileType::RelevantTokensFile => self.relevant_tokens_filename.clone(),
+			FileType::ResultsFile => self.result_tokens_filename.clone(),
+		}.expect("No input file has been set");
+		let f = open_input(&filename).expect("Input trec file could not be read.");
+		let mut res: HashMap<String, Vec<RelevantToken>> = HashMap::new();
+		// Lines of a query need not be consecutive, as with trec_eval, so blocks are finished once the
+		// whole file has been read; streamed result blocks are finished as soon as the next query starts.
+		let streamed = ft == FileType::ResultsFile && self.streaming;
+		let mut blocks: Vec<(String, Vec<RelevantToken>)> = Vec::new();
+		let mut positions: HashMap<String, usize> = HashMap::new();
+		for buffer in f.lines() {
+			let current_line = buffer.unwrap();
+			if current_line.trim().is_empty() { continue; }
+			let (query_name, tok) = self.parse_trec_line(&current_line, ft);
+			if streamed && blocks.last().is_some_and( |b| b.0 != query_name ) {
+				let (q, mut tokens) = blocks.pop().unwrap();
+				positions.remove(&q);
+				self.finish_trec_block(ft, &q, &mut tokens);
+				res.insert(q, tokens);
+			}
+			let k = match positions.get(&query_name) {
+				Some(&k) => k,
+				None => {
+					if res.contains_key(&query_name) {
+						panic!("Result file {} is not grouped by query id (query {}), which --streaming requires; sort it or evaluate it without --streaming",
+							filename, query_name);
+					}
+					blocks.push((query_name.clone(), Vec::new()));
+					positions.insert(query_name.clone(), blocks.len() - 1);
+					blocks.len() - 1
+				},
+			};
+			if let Some(tok) = tok {
+				if ft == FileType::RelevantTokensFile || !self.streaming || self.is_selected(&query_name) {
+					blocks[k].1.push(tok);
+				}
+			}
+		}
+		for (q, mut tokens) in blocks {
+			self.finish_trec_block(ft, &q, &mut tokens);
+			res.insert(q, tokens);
+		}
+		match ft {
+			FileType::RelevantTokensFile => self.relevant_tokens = Some(res),
+			FileType::ResultsFile => self.result_tokens = Some(res),
+		};
+	}
+}
+
+impl RetrievalData {
+	fn finish_trec_block(&mut self, ft: FileType, queryname: &str, tokens: &mut Vec<RelevantToken>) {
+		if ft == FileType::ResultsFile {
+			// Stable, so that tokens of equal similarity keep their order in the file
+			tokens.sort_by( |a, b| b.get_score().partial_cmp(&a.get_score()).unwrap_or(Ordering::Equal) );
+		}
+		self.finish_query_block(ft, queryname, tokens);
+	}
+}
+
+/// Reads the relevance (`ft` = RelevantTokensFile) or result file of `data`, in the given format,
+/// or in the format detected from the file contents if `format` is None.
+pub fn parse_input(data: &mut RetrievalData, ft: FileType, format: Option<FileFormat>) {
+	let format = match format {
+		Some(f) => f,
+		None => {
+			let filename = match ft {
+				FileType::RelevantTokensFile => data.relevant_tokens_filename.clone(),
+				FileType::ResultsFile => data.result_tokens_filename.clone(),
+			}.expect("No input file has been set");
+			// The standard input can't be read twice; it is expected to be in the ICFHR'14 format
+			if filename == "-" { FileFormat::XmlICFHR14 } else { detect_format(&filename).expect("Input file could not be read.").0 }
+		},
+	};
+	match format {
+		FileFormat::XmlICFHR14 => data.parse_file(ft),
+		FileFormat::TrecEval => data.parse_trec_file(ft),
+	}
+}
+
+/// Reads a list of query ids, one per line.
+pub fn read_query_list(fname: &str) -> HashSet<String> {
+	let f = open_input(fname).expect("Query list could not be read.");
+	f.lines()
+		.map( |l| l.unwrap() )
+		.filter_map( |l| l.split_whitespace().next().map(String::from) )
+		.collect()
+}
+
+/// Reads a file with a query id followed by a group label on each line.
+/// A query may appear on several lines, if it belongs to more than one group.
+pub fn read_query_groups(fname: &str) -> HashMap<String, Vec<String>> {
+	let f = open_input(fname).expect("Query groups file could not be read.");
+	let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+	for buffer in f.lines() {
+		let current_line = buffer.unwrap();
+		let mut fields = current_line.trim().splitn(2, char::is_whitespace);
+		if let (Some(queryname), Some(label)) = (fields.next(), fields.next()) {
+			groups.entry(String::from(queryname)).or_default().push(String::from(label.trim()));
+		}
+	}
+	groups
+}
+
+#[cfg(test)]
+pub fn load_fixtures(load_results: bool) -> RetrievalData {
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	if load_results {
+		f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+		f.parse_file(FileType::ResultsFile);
+	}
+	f
+}
+
+#[cfg(test)]
+pub fn load_fixtures_ties() -> RetrievalData {
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsTiesSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	f
+}
+
+#[cfg(test)]
+pub fn load_fixtures_bentham(load_results: bool) -> RetrievalData {
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/TRACK_I_Bentham_ICFHR2014.RelevanceJudgements.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	if load_results {
+		open_input("fixtures/G1_TRACK_I_Bentham.xml.gz")
+		.expect("Can't read the fixture fixtures/G1_TRACK_I_Bentham.xml.gz; please follow the instructions found in README.md");
+		f.set_results_filename(String::from("fixtures/G1_TRACK_I_Bentham.xml.gz"));
+		f.parse_file(FileType::ResultsFile);
+	}
+	f
+}
+
+#[cfg(test)]
+use metrics::BenchmarkResult;
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checkquerynames() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	let num_queries = res.len();
+	assert!(res.contains_key("sb0000"));
+	assert!(res.contains_key("sb0001"));
+	assert_eq!(num_queries, 2);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_1() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert!(res.values().all( |relevants| relevants.len() == 9 ));
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_x() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	for token in res.get("sb0000").unwrap().iter() {
+		match token.id {
+			//Regarding 'ref', see http://stackoverflow.com/a/28159407/5615276
+			// and http://rustbyexample.com/scope/borrow/ref.html
+			TokenId::BoundingBox(ref bb) => println!("{:?}", bb.min().get_x()),
+			_ 	 => panic!("Expected a bounding box"),
+		}
+	}
+	assert_eq!(res.get("sb0000").unwrap().iter().fold(0, |acc, token|
+		match token.id {
+			TokenId::BoundingBox(ref bb) => acc + bb.min().get_x(),
+			_ 	 => acc,
+		}
+	), 6435);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_y() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
+		match token.id {
+			TokenId::BoundingBox(ref bb) => acc + bb.min().get_y(),
+			_ 	 => acc,
+		}
+	), 10921);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_width() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
+		match token.id {
+			TokenId::BoundingBox(ref bb) => acc + bb.width(),
+			_ 	 => acc,
+		}
+	), 2175);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_height() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert_eq!(res.get("sb0001").unwrap().iter().fold(0, |acc, token|
+		match token.id {
+			TokenId::BoundingBox(ref bb) => acc + bb.height(),
+			_ 	 => acc,
+		}
+	), 987);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_relevance_0() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert_eq!(res.get("sb0000").unwrap().iter().fold(0.0, |acc, token|
+		acc + token.get_relevance()
+	), 8.0);
+}
+
+#[test]
+fn test_parserelevants_type_icfhr14xml_checksums_relevance_1() {
+	let f = load_fixtures(false);
+	let res = f.relevant_tokens.unwrap();
+	assert_eq!(res.get("sb0001").unwrap().iter().fold(0.0, |acc, token|
+		acc + token.get_relevance()
+	), 8.0);
+	//NOTE: there is a syntax error on the sample file ("elevance" instead of "Relevance")
+	// This version of the parser will read relevance despite the syntax error;
+	// a previous version of this souce didn't though, so the '8.0' on the assert had to be '7.0'.
+}
+
+#[test]
+fn test_parseresults_type_icfhr14xml_checksums_width() {
+	let f = load_fixtures(true);
+	let res = f.result_tokens.unwrap();
+	let token = &res.get("sb0001").unwrap()[0];
+	match token.id {
+		TokenId::BoundingBox(ref bb) => assert_eq!(bb.width(), 180),
+		_ 	 => panic!("Expected a bounding box"),
+	};
+	let token = res.get("sb0001").unwrap().last().unwrap();
+	match token.id {
+		TokenId::BoundingBox(ref bb) => assert_eq!(bb.width(), 278),
+		_ 	 => panic!("Expected a bounding box"),
+	};
+}
+
+#[test]
+fn test_parseresults_type_icfhr14xml_scores() {
+	let f = load_fixtures_ties();
+	let res = f.result_tokens.unwrap();
+	let q0 = res.get("sb0000").unwrap();
+	assert_eq!(q0[0].get_score(), Some(0.9));
+	assert_eq!(q0[9].get_score(), Some(0.2));
+	let f = load_fixtures(true);
+	let res = f.result_tokens.unwrap();
+	assert_eq!(res.get("sb0000").unwrap()[0].get_score(), None);
+}
+
+#[test]
+fn test_exclude_query_instance_from_file() {
+	let mut f = RetrievalData::new();
+	f.set_query_instances_filename(String::from("fixtures/QueryInstancesSample.xml"));
+	f.parse_query_instances_file();
+	assert_eq!(f.query_instances.len(), 1);
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	// The query instance of sb0000 is both relevant and retrieved first
+	assert_eq!(f.num_relevants("sb0000"), 8);
+	assert_eq!(f.num_relevants("sb0001"), 9);
+	let res = f.result_tokens.unwrap();
+	match res.get("sb0000").unwrap()[0].id {
+		TokenId::BoundingBox(ref bb) => assert_eq!(bb.min().get_x(), 860),
+		_ 	 => panic!("Expected a bounding box"),
+	};
+	match res.get("sb0001").unwrap()[0].id {
+		TokenId::BoundingBox(ref bb) => assert_eq!(bb.min().get_x(), 860),
+		_ 	 => panic!("Expected a bounding box"),
+	};
+	let all = load_fixtures(true).result_tokens.unwrap();
+	assert_eq!(res.get("sb0000").unwrap().len() + 1, all.get("sb0000").unwrap().len());
+}
+
+#[test]
+fn test_exclude_query_instance_from_query_element() {
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsQbESample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	assert!(f.query_instances.contains_key("sb0000"));
+	assert_eq!(f.num_relevants("sb0000"), 8);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	let q0 = f.benchmark_results.get("sb0000").unwrap();
+	match *q0.get("precAt5").unwrap() {
+		BenchmarkResult::Scalar(x) => assert_eq!(x, 0.8),
+		_ => panic!("Expected a scalar"),
+	};
+}
+
+#[test]
+fn test_parseresults_compressed() {
+	let plain = load_fixtures(true);
+	for ext in ["gz", "bz2", "zst"].iter() {
+		let mut f = load_fixtures(false);
+		f.set_results_filename(format!("fixtures/WordSpottingResultsSample.xml.{}", ext));
+		f.parse_file(FileType::ResultsFile);
+		for (queryname, results) in plain.result_tokens.as_ref().unwrap().iter() {
+			assert_eq!(f.result_tokens.as_ref().unwrap().get(queryname).unwrap().len(), results.len());
+		}
+		match f.compute_average_benchmark("ap") {
+			BenchmarkResult::Scalar(x) => assert!((x - 0.51852).abs() < 0.0001),
+			_ => panic!("Expected a scalar"),
+		};
+	}
+}
+
+#[test]
+fn test_parseresults_trec_ungrouped() {
+	use std::{env, fs};
+	use writers::WriterTrecEval;
+	let plain = load_fixtures(true);
+	let mut run: Vec<u8> = Vec::new();
+	plain.write_trec(FileType::ResultsFile, &mut run).unwrap();
+	// Interleave the lines of the queries, by rank
+	let mut lines: Vec<&str> = std::str::from_utf8(&run).unwrap().lines().collect();
+	lines.sort_by_key( |l| l.split_whitespace().nth(3).unwrap().parse::<usize>().unwrap() );
+	assert_ne!(lines[0].split_whitespace().next(), lines[1].split_whitespace().next());
+	let fname = env::temp_dir().join(format!("rusteval-ungrouped-{}.trec", std::process::id()));
+	fs::write(&fname, lines.join("\n")).unwrap();
+	let mut f = load_fixtures(false);
+	f.set_results_filename(String::from(fname.to_str().unwrap()));
+	f.parse_trec_file(FileType::ResultsFile);
+	fs::remove_file(&fname).unwrap();
+	assert_eq!(f.benchmark_results.len(), plain.benchmark_results.len());
+	for b in plain.computed_benchmarks() {
+		assert_eq!(f.compute_average_benchmark(&b).scalar(), plain.compute_average_benchmark(&b).scalar(), "{}", b);
+	}
+}
+
+#[test]
+fn test_parseresults_streaming() {
+	let plain = load_fixtures(true);
+	let mut f = load_fixtures(false);
+	f.set_streaming(None);
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	assert!(f.result_tokens.as_ref().unwrap().values().all( |results| results.is_empty() ));
+	for b in plain.computed_benchmarks() {
+		assert_eq!(f.compute_average_benchmark(&b).scalar(), plain.compute_average_benchmark(&b).scalar());
+	}
+	// Only the top tokens are kept, and tokens beyond the depth are not even stored
+	let mut f = RetrievalData::new();
+	f.set_query_instances_filename(String::from("fixtures/QueryInstancesSample.xml"));
+	f.parse_query_instances_file();
+	f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	f.parse_file(FileType::RelevantTokensFile);
+	f.set_depth(5);
+	f.set_streaming(Some(3));
+	f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	f.parse_file(FileType::ResultsFile);
+	assert!(f.result_tokens.as_ref().unwrap().values().all( |results| results.len() == 3 ));
+	let mut g = RetrievalData::new();
+	g.set_query_instances_filename(String::from("fixtures/QueryInstancesSample.xml"));
+	g.parse_query_instances_file();
+	g.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+	g.parse_file(FileType::RelevantTokensFile);
+	g.set_depth(5);
+	g.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+	g.parse_file(FileType::ResultsFile);
+	for b in g.computed_benchmarks() {
+		assert_eq!(f.compute_average_benchmark(&b).scalar(), g.compute_average_benchmark(&b).scalar());
+	}
+	// Tokens tied with the last one above the cut-off are kept for the tie-aware metrics
+	let load = |streaming: bool| {
+		let mut f = RetrievalData::new();
+		f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+		f.parse_file(FileType::RelevantTokensFile);
+		f.set_depth(4);
+		if streaming { f.set_streaming(None); }
+		f.set_results_filename(String::from("fixtures/WordSpottingResultsTiesSample.xml"));
+		f.parse_file(FileType::ResultsFile);
+		f
+	};
+	let (streamed, plain) = (load(true), load(false));
+	for b in plain.computed_benchmarks() {
+		assert_eq!(streamed.compute_average_benchmark(&b).scalar(), plain.compute_average_benchmark(&b).scalar(), "{}", b);
+	}
+	// Results removed by the collection are removed before the cut-off, in both modes
+	use collection::{Collection, BoundsPolicy};
+	let load = |streaming: bool| {
+		let mut f = RetrievalData::new();
+		f.set_collection(Collection::from_file("fixtures/CollectionSample.txt", BoundsPolicy::Clip));
+		f.set_relevants_filename(String::from("fixtures/GroundTruthRelevanceJudgementsSample.xml"));
+		f.parse_file(FileType::RelevantTokensFile);
+		f.set_depth(10);
+		if streaming { f.set_streaming(None); }
+		f.set_results_filename(String::from("fixtures/WordSpottingResultsSample.xml"));
+		f.parse_file(FileType::ResultsFile);
+		f
+	};
+	let (streamed, plain) = (load(true), load(false));
+	assert!(plain.collection().unwrap().retrieved.unknown_page > 0);
+	for b in plain.computed_benchmarks() {
+		assert_eq!(streamed.compute_average_benchmark(&b).scalar(), plain.compute_average_benchmark(&b).scalar(), "{}", b);
+	}
+}
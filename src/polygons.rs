@@ -0,0 +1,140 @@
+//! Polygonal token regions, e.g. rotated word boxes on skewed lines.
+//!
+//! Vertices are continuous pixel coordinates. A `Rectangle` with `min` (x, y) and size
+//! (w, h) covers the pixels x to x + w - 1, that is the region from x to x + w, so that
+//! `Polygon::from_rectangle` has the same area as the rectangle.
+use rectangles::Rectangle;
+
+type Vertex = (f64, f64);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon {
+	points: Vec<(u32, u32)>,
+}
+
+/// Twice the signed area of a polygon; positive for counter-clockwise vertices (with y pointing up).
+fn signed_area2(points: &[Vertex]) -> f64 {
+	(0..points.len())
+		.map( |i| {
+			let (a, b) = (points[i], points[(i + 1) % points.len()]);
+			a.0 * b.1 - b.0 * a.1
+		})
+		.sum()
+}
+
+/// Clips `subject` by the convex, counter-clockwise polygon `clip` (Sutherland-Hodgman).
+fn clip_convex(subject: &[Vertex], clip: &[Vertex]) -> Vec<Vertex> {
+	let side = |a: Vertex, b: Vertex, p: Vertex| (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0);
+	let mut output = subject.to_vec();
+	for i in 0..clip.len() {
+		let (a, b) = (clip[i], clip[(i + 1) % clip.len()]);
+		let input = output;
+		output = Vec::with_capacity(input.len() + 1);
+		for j in 0..input.len() {
+			let (p, q) = (input[j], input[(j + 1) % input.len()]);
+			let (sp, sq) = (side(a, b, p), side(a, b, q));
+			if sp >= 0.0 { output.push(p); }
+			if (sp >= 0.0) != (sq >= 0.0) {
+				let t = sp / (sp - sq);
+				output.push((p.0 + t * (q.0 - p.0), p.1 + t * (q.1 - p.1)));
+			}
+		}
+		if output.is_empty() { break; }
+	}
+	output
+}
+
+/// The triangles from `origin` over the edges of a polygon, counter-clockwise, with the sign of their area.
+fn fan(points: &[Vertex], origin: Vertex) -> Vec<([Vertex; 3], f64)> {
+	(0..points.len())
+		.filter_map( |i| {
+			let (a, b) = (points[i], points[(i + 1) % points.len()]);
+			let area2 = signed_area2(&[origin, a, b]);
+			if area2 > 0.0 { Some(([origin, a, b], 1.0)) }
+			else if area2 < 0.0 { Some(([origin, b, a], -1.0)) }
+			else { None }
+		})
+		.collect()
+}
+
+impl Polygon {
+	/// A polygon with the given vertices, in either orientation; panics with fewer than three vertices.
+	pub fn new(points: Vec<(u32, u32)>) -> Polygon {
+		if points.len() < 3 { panic!("A polygon needs at least three vertices"); }
+		Polygon { points }
+	}
+	/// The polygon around the pixels of a rectangle; a rectangle reaching the last coordinate loses its last pixel.
+	pub fn from_rectangle(rect: &Rectangle) -> Polygon {
+		let (x0, y0) = (rect.min().get_x(), rect.min().get_y());
+		let (x1, y1) = (rect.max().get_x().saturating_add(1), rect.max().get_y().saturating_add(1));
+		Polygon::new(vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)])
+	}
+	pub fn points(&self) -> &[(u32, u32)] { &self.points }
+	/// The smallest rectangle containing all the pixels of the polygon.
+	pub fn bounding_box(&self) -> Rectangle {
+		let xs = || self.points.iter().map( |p| p.0 );
+		let ys = || self.points.iter().map( |p| p.1 );
+		let (x0, y0) = (xs().min().unwrap(), ys().min().unwrap());
+		Rectangle::from_min_size(x0, y0, (xs().max().unwrap() - x0).max(1), (ys().max().unwrap() - y0).max(1))
+			.expect("Polygon beyond the coordinate range")
+	}
+	fn vertices(&self) -> Vec<Vertex> {
+		self.points.iter().map( |&(x, y)| (x as f64, y as f64) ).collect()
+	}
+	pub fn area(&self) -> f64 {
+		signed_area2(&self.vertices()).abs() / 2.0
+	}
+	/// The area of the intersection with `other`. Polygons need not be convex: both are decomposed
+	/// into signed triangles from a common origin, and their pairwise intersections are summed.
+	pub fn intersection_area(&self, other: &Polygon) -> f64 {
+		let (a, b) = (self.vertices(), other.vertices());
+		let origin = a[0];
+		let (fan_a, fan_b) = (fan(&a, origin), fan(&b, origin));
+		let mut area2 = 0.0;
+		for &(ref ta, sa) in &fan_a {
+			for &(ref tb, sb) in &fan_b {
+				let clipped = clip_convex(ta, tb);
+				if clipped.len() >= 3 { area2 += sa * sb * signed_area2(&clipped).abs(); }
+			}
+		}
+		// Orientations cancel out, whatever those of the polygons
+		let orientation = signed_area2(&a).signum() * signed_area2(&b).signum();
+		(orientation * area2 / 2.0).max(0.0)
+	}
+	/// Intersection over union; 0 for polygons of zero area.
+	pub fn iou(&self, other: &Polygon) -> f64 {
+		let intersection = self.intersection_area(other);
+		let union = self.area() + other.area() - intersection;
+		if union > 0.0 { intersection / union } else { 0.0 }
+	}
+}
+
+#[test]
+fn test_polygon_geometry() {
+	let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+	let square = Polygon::new(vec![(0, 0), (10, 0), (10, 10), (0, 10)]);
+	assert_eq!(square.area(), 100.0);
+	// Orientation doesn't matter
+	let shifted = Polygon::new(vec![(5, 5), (5, 15), (15, 15), (15, 5)]);
+	assert!(close(square.intersection_area(&shifted), 25.0));
+	assert!(close(shifted.intersection_area(&square), 25.0));
+	assert!(close(square.iou(&shifted), 25.0 / 175.0));
+	assert!(close(square.iou(&square), 1.0));
+	assert!(close(square.intersection_area(&Polygon::new(vec![(20, 20), (30, 20), (30, 30)])), 0.0));
+	// A non-convex L shape, and a diamond rotated by 45 degrees
+	let l_shape = Polygon::new(vec![(0, 0), (10, 0), (10, 4), (4, 4), (4, 10), (0, 10)]);
+	assert_eq!(l_shape.area(), 64.0);
+	assert!(close(l_shape.intersection_area(&shifted), 0.0));
+	assert!(close(l_shape.intersection_area(&square), 64.0));
+	let diamond = Polygon::new(vec![(5, 0), (10, 5), (5, 10), (0, 5)]);
+	assert_eq!(diamond.area(), 50.0);
+	assert!(close(diamond.intersection_area(&l_shape), 27.5));
+	// Rectangles cover whole pixels
+	let rect = Rectangle::from_min_size(159, 1775, 184, 89).unwrap();
+	let from_rect = Polygon::from_rectangle(&rect);
+	assert_eq!(from_rect.area(), rect.area() as f64);
+	let bb = from_rect.bounding_box();
+	assert_eq!((bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()), (159, 1775, 184, 89));
+	let edge = Rectangle::from_min_max(u32::MAX - 9, 0, u32::MAX, 9).unwrap();
+	assert_eq!(Polygon::from_rectangle(&edge).area(), 90.0);
+}
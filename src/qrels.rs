@@ -0,0 +1,221 @@
+//! Generation of relevance judgements from transcribed word segmentations (`rusteval make-qrels`).
+//!
+//! An annotation file holds one transcribed word box per line:
+//!
+//! ```text
+//! # document x y width height transcription
+//! 027_029_001 159 1775 184 89 Bentham
+//! ```
+//!
+//! Fields are separated by any whitespace, and lines starting with `#` are comments, as in
+//! keyword query files. PAGE XML, ALTO and hOCR files, or directories of them, may be read instead.
+//! Queries are either keywords, one `queryid keyword` pair per line, or query instances
+//! in the `<Queries>` format of `--query-instances`, whose keyword is the transcription of
+//! the annotated word box they refer to. A word is relevant to a query if its normalized
+//! transcription equals the normalized keyword.
+use std::io::BufRead;
+
+use input::open_input;
+use layout::read_layouts;
+use parsers::ParserXmlICFHR14;
+use rectangles::Rectangle;
+use retrieval::RetrievalData;
+use tokens::{RelevantToken, TokenId};
+use writers::encode_docno;
+
+/// Replacements of the letters U+00C0 to U+00FF; letters without a base letter are kept.
+const LATIN_1: &str = "AAAAAAÆCEEEEIIIIÐNOOOOO×OUUUUYÞßaaaaaaæceeeeiiiiðnooooo÷ouuuuyþy";
+/// Replacements of the letters U+0100 to U+017F; the long s becomes an s.
+const LATIN_EXTENDED_A: &str = concat!("AaAaAaCcCcCcCcDdDdEeEeEeEeEeGgGgGgGgHhHhIiIiIiIiIiĲĳJjKkĸLlLlLlL",
+	"lLlNnNnNnŉŊŋOoOoOoŒœRrRrRrSsSsSsSsTtTtTtUuUuUuUuUuUuWwYyYZzZzZzs");
+
+/// Removes the diacritics of `c`, for Latin letters; None for combining marks.
+fn strip_diacritic(c: char) -> Option<char> {
+	match c as u32 {
+		0x300..=0x36f => None,
+		n @ 0xc0..=0xff => LATIN_1.chars().nth(n as usize - 0xc0),
+		n @ 0x100..=0x17f => LATIN_EXTENDED_A.chars().nth(n as usize - 0x100),
+		_ => Some(c),
+	}
+}
+
+/// How transcriptions and keywords are normalized before they are compared.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Normalization {
+	pub case: bool,
+	pub punctuation: bool,
+	pub diacritics: bool,
+}
+
+impl Normalization {
+	/// Parses a comma-separated list of `case`, `punctuation` and `diacritics`, or `none`.
+	pub fn from_names(list: &str) -> Option<Normalization> {
+		let mut norm = Normalization::default();
+		for name in list.split(',').map( |n| n.trim() ) {
+			match name {
+				"case" => norm.case = true,
+				"punctuation" => norm.punctuation = true,
+				"diacritics" => norm.diacritics = true,
+				"none" => {},
+				_ => return None,
+			}
+		}
+		Some(norm)
+	}
+	/// Folds case, strips punctuation and diacritics, as configured, and collapses whitespace.
+	pub fn normalize(&self, text: &str) -> String {
+		let mut out = String::new();
+		for c in text.chars() {
+			let c = if self.diacritics {
+				match strip_diacritic(c) { Some(c) => c, None => continue }
+			} else { c };
+			if self.punctuation && !c.is_alphanumeric() && !c.is_whitespace() { continue; }
+			if self.case { out.extend(c.to_lowercase()); } else { out.push(c); }
+		}
+		out.split_whitespace().collect::<Vec<&str>>().join(" ")
+	}
+}
+
+/// A query of `make_qrels`.
+#[derive(Debug)]
+pub enum QuerySpec {
+	Keyword(String),
+	/// A word box of the annotation; its transcription is the keyword.
+	Instance(RelevantToken),
+}
+
+/// Reads an annotation file, see the module documentation for its format, or the transcribed
+/// words of a PAGE XML, ALTO or hOCR file, or of a directory of such files (see `layout`).
+pub fn read_annotations(fname: &str) -> Vec<RelevantToken> {
+	if let Some(pages) = read_layouts(fname).expect("Annotation file could not be read.") {
+		return pages.into_iter()
+			.flat_map( |p| p.words )
+			.filter( |tok| tok.get_transcription().is_some() )
+			.collect();
+	}
+	let f = open_input(fname).expect("Annotation file could not be read.");
+	let mut words = Vec::new();
+	for (n, buffer) in f.lines().enumerate() {
+		let current_line = buffer.unwrap();
+		if current_line.trim().is_empty() || current_line.trim_start().starts_with('#') { continue; }
+		// Fields may be separated by any whitespace; the transcription is the rest of the line
+		let mut fields: Vec<&str> = current_line.split_whitespace().collect();
+		if fields.len() < 6 {
+			panic!("Line {} of {} is not a transcribed word box", n + 1, fname);
+		}
+		let transcription = fields.split_off(5).join(" ");
+		let values: Vec<u32> = fields[1..5].iter()
+			.map( |v| v.parse().unwrap_or_else( |_| panic!("Invalid number on line {} of {}", n + 1, fname) ) )
+			.collect();
+		let rect = Rectangle::from_min_size(values[0], values[1], values[2], values[3])
+			.unwrap_or_else( |e| panic!("Invalid word box on line {} of {}: {}", n + 1, fname, e) );
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::BoundingBox(rect))
+			.set_document(String::from(fields[0]))
+			.set_transcription(transcription);
+		words.push(tok);
+	}
+	words
+}
+
+/// Reads queries from a file of `queryid keyword` lines, or of query instances in the `<Queries>` format.
+pub fn read_queries(fname: &str) -> Vec<(String, QuerySpec)> {
+	let lines: Vec<String> = open_input(fname).expect("Query file could not be read.")
+		.lines().map( |l| l.unwrap() ).collect();
+	let mut queries: Vec<(String, QuerySpec)> = Vec::new();
+	if lines.iter().any( |l| l.contains("<Query ") ) {
+		let mut f = RetrievalData::new();
+		f.set_query_instances_filename(String::from(fname));
+		f.parse_query_instances_file();
+		queries.extend(f.query_instances.into_iter().map( |(q, tok)| (q, QuerySpec::Instance(tok)) ));
+	} else {
+		for current_line in lines.iter().filter( |l| !l.trim_start().starts_with('#') ) {
+			let mut fields = current_line.trim().splitn(2, char::is_whitespace);
+			if let (Some(queryname), Some(keyword)) = (fields.next(), fields.next()) {
+				queries.push((String::from(queryname), QuerySpec::Keyword(String::from(keyword.trim()))));
+			}
+		}
+	}
+	queries.sort_by( |a, b| a.0.cmp(&b.0) );
+	if let Some(w) = queries.windows(2).find( |w| w[0].0 == w[1].0 ) {
+		panic!("Query {} appears more than once in {}", w[0].0, fname);
+	}
+	queries
+}
+
+/// Builds the relevance judgements of `queries` over the annotated `words`.
+/// Also returns the queries without any relevant word, which are left out of the judgements.
+pub fn make_qrels(words: &[RelevantToken], queries: &[(String, QuerySpec)], norm: Normalization) -> (RetrievalData, Vec<String>) {
+	let transcriptions: Vec<String> = words.iter()
+		.map( |w| norm.normalize(w.get_transcription().map_or("", |t| t.as_str())) )
+		.collect();
+	let mut qrels = RetrievalData::new();
+	let mut unmatched = Vec::new();
+	for (queryname, query) in queries {
+		let keyword = match *query {
+			QuerySpec::Keyword(ref k) => Some(norm.normalize(k)),
+			QuerySpec::Instance(ref instance) => {
+				let docno = encode_docno(instance);
+				words.iter().position( |w| encode_docno(w) == docno ).map( |i| transcriptions[i].clone() )
+			},
+		};
+		let relevant: Vec<&RelevantToken> = match keyword {
+			Some(ref k) if !k.is_empty() => words.iter().zip(&transcriptions)
+				.filter( |&(_, t)| t == k )
+				.map( |(w, _)| w )
+				.collect(),
+			_ => Vec::new(),
+		};
+		if relevant.is_empty() {
+			unmatched.push(queryname.clone());
+			continue;
+		}
+		for tok in relevant {
+			qrels.add_relevant_token(queryname, tok.clone());
+		}
+		if let QuerySpec::Instance(ref instance) = *query {
+			qrels.set_query_instance(queryname, instance.clone());
+		}
+	}
+	(qrels, unmatched)
+}
+
+#[test]
+fn test_normalization() {
+	assert_eq!((LATIN_1.chars().count(), LATIN_EXTENDED_A.chars().count()), (0x40, 0x80));
+	let all = Normalization::from_names("case,punctuation,diacritics").unwrap();
+	assert_eq!(all.normalize("Benthàm,"), "bentham");
+	assert_eq!(all.normalize("  Ça  va? "), "ca va");
+	assert_eq!(all.normalize("Priſon"), "prison");
+	// Decomposed diacritics are combining marks
+	assert_eq!(all.normalize("Bentha\u{300}m"), "bentham");
+	assert_eq!(Normalization::from_names("case").unwrap().normalize("Bentham."), "bentham.");
+	assert_eq!(Normalization::default().normalize("Benthàm"), "Benthàm");
+	assert_eq!(Normalization::from_names("case,accents"), None);
+}
+
+#[test]
+fn test_make_qrels() {
+	let words = read_annotations("fixtures/AnnotationSample.txt");
+	assert_eq!(words.len(), 10);
+	// Fields may be separated by tabs and repeated spaces
+	assert_eq!(encode_docno(&words[9]), "096_008_002_600_1209_300_195");
+	assert_eq!(words[9].get_transcription().map( |t| t.as_str() ), Some("Panopticon letters"));
+	let queries = read_queries("fixtures/QueryKeywordsSample.txt");
+	let count = |qrels: &RetrievalData, q: &str| qrels.relevant_tokens().and_then( |r| r.get(q) ).map_or(0, |t| t.len());
+
+	let (qrels, unmatched) = make_qrels(&words, &queries, Normalization::default());
+	assert_eq!((count(&qrels, "qs0000"), count(&qrels, "qs0001")), (0, 1));
+	assert_eq!(unmatched, vec!["qs0000", "qs0002"]);
+
+	let (qrels, unmatched) = make_qrels(&words, &queries, Normalization::from_names("case,punctuation,diacritics").unwrap());
+	assert_eq!((count(&qrels, "qs0000"), count(&qrels, "qs0001")), (4, 3));
+	assert_eq!(unmatched, vec!["qs0002"]);
+
+	// Query-by-example queries take the transcription of their instance, and record it
+	let queries = read_queries("fixtures/QueryInstancesKeywordSample.xml");
+	let (qrels, unmatched) = make_qrels(&words, &queries, Normalization::from_names("case,punctuation").unwrap());
+	assert!(unmatched.is_empty());
+	assert_eq!(count(&qrels, "qe0000"), 2);
+	assert!(qrels.query_instances.contains_key("qe0000"));
+}
@@ -1,75 +1,193 @@
-#[derive(Debug)]
+//! Pixel rectangles.
+//!
+//! Coordinates are pixel indices and `max` is inclusive: the last pixel covered. A rectangle
+//! with `min` (x, y) and size (w, h) thus has `max` (x + w - 1, y + h - 1), and covers the
+//! region from x to x + w in continuous coordinates. Rectangles cover at least one pixel.
+use std::error::Error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Point {
-	x: Option<u32>,
-	y: Option<u32>,
+	x: u32,
+	y: u32,
 }
 
 impl Point {
-	fn new() -> Point { 
-		Point { x: None, y: None }
-	}
-	pub fn get_x(&self) -> u32 {
-		match self.x {
-			Some(v) => v,
-			None 	=> panic!("Undefined value")
-		}
-	}
-	pub fn get_y(&self) -> u32 {
-		match self.y {
-			Some(v) => v,
-			None 	=> panic!("Undefined value")
-		}
+	pub fn new(x: u32, y: u32) -> Point { Point { x, y } }
+	pub fn get_x(&self) -> u32 { self.x }
+	pub fn get_y(&self) -> u32 { self.y }
+}
+
+/// Why a rectangle could not be built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RectangleError {
+	/// A width or height of zero.
+	Empty,
+	/// A `max` corner before the `min` corner.
+	Inverted,
+	/// A corner or a size that does not fit in 32 bits.
+	Overflow,
+}
+
+impl fmt::Display for RectangleError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match *self {
+			RectangleError::Empty => "rectangle has a zero width or height",
+			RectangleError::Inverted => "rectangle has its max corner before its min corner",
+			RectangleError::Overflow => "rectangle extends beyond the 32-bit coordinate range",
+		})
 	}
 }
 
-#[derive(Debug)]
+impl Error for RectangleError {}
+
+/// A rectangle of pixels, from `min` to `max` inclusive; see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Rectangle {
-	pub min: Point,
-	pub max: Point,
+	min: Point,
+	max: Point,
 }
 
 impl Rectangle {
-	pub fn width(&self) -> u32 { self.max.get_x() - self.min.get_x() + 1 }
-	pub fn height(&self)-> u32 { self.max.get_y() - self.min.get_y() + 1 }
-	fn area(&self)  -> u32 { self.width()*self.height() }
-	pub fn is_ok(&self) -> bool { self.max.get_x() > self.min.get_x() && self.max.get_y() > self.min.get_y() }
-
-	pub fn new() -> Rectangle { Rectangle { min: Point::new(), max: Point::new() } }
-	pub fn set_min(&mut self, x: u32, y: u32) -> &mut Rectangle { 
-		if let (Some(max_x), Some(max_y)) = (self.max.x, self.max.y) {
-			if max_x <= x || max_y <= y { panic!("Rectangle dimensions non-positive!"); }
+	/// The rectangle of `w` by `h` pixels whose top left pixel is (x, y).
+	pub fn from_min_size(x: u32, y: u32, w: u32, h: u32) -> Result<Rectangle, RectangleError> {
+		if w == 0 || h == 0 { return Err(RectangleError::Empty); }
+		match (x.checked_add(w - 1), y.checked_add(h - 1)) {
+			(Some(x1), Some(y1)) => Ok(Rectangle::from_corners(x, y, x1, y1)),
+			_ => Err(RectangleError::Overflow),
 		}
-		self.min.x = Some(x); 
-		self.min.y = Some(y);		
-		self
 	}
-	pub fn set_max(&mut self, x: u32, y: u32) -> &mut Rectangle { 
-		if let (Some(min_x), Some(min_y)) = (self.min.x, self.min.y) {
-			if min_x >= x || min_y >= y { panic!("Rectangle dimensions non-positive!"); }
-		}		
-		self.max.x = Some(x);
-		self.max.y = Some(y);		
-		self
+	/// The rectangle from pixel (x0, y0) to pixel (x1, y1), both included.
+	pub fn from_min_max(x0: u32, y0: u32, x1: u32, y1: u32) -> Result<Rectangle, RectangleError> {
+		if x1 < x0 || y1 < y0 { return Err(RectangleError::Inverted); }
+		// The width and height must fit in 32 bits too
+		if x1 - x0 == u32::MAX || y1 - y0 == u32::MAX { return Err(RectangleError::Overflow); }
+		Ok(Rectangle::from_corners(x0, y0, x1, y1))
+	}
+	fn from_corners(x0: u32, y0: u32, x1: u32, y1: u32) -> Rectangle {
+		Rectangle { min: Point::new(x0, y0), max: Point::new(x1, y1) }
+	}
+
+	/// The top left pixel.
+	pub fn min(&self) -> Point { self.min }
+	/// The bottom right pixel, included in the rectangle.
+	pub fn max(&self) -> Point { self.max }
+	pub fn width(&self) -> u32 { self.max.x - self.min.x + 1 }
+	pub fn height(&self)-> u32 { self.max.y - self.min.y + 1 }
+	pub fn area(&self)  -> u64 { self.width() as u64 * self.height() as u64 }
+
+	/// The pixels covered by both rectangles, if any.
+	pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+		let (x0, y0) = (self.min.x.max(other.min.x), self.min.y.max(other.min.y));
+		let (x1, y1) = (self.max.x.min(other.max.x), self.max.y.min(other.max.y));
+		if x0 > x1 || y0 > y1 { None } else { Some(Rectangle::from_corners(x0, y0, x1, y1)) }
+	}
+	pub fn intersection_area(&self, other: &Rectangle) -> u64 {
+		self.intersection(other).map_or(0, |r| r.area())
+	}
+	/// The number of pixels covered by either rectangle.
+	pub fn union_area(&self, other: &Rectangle) -> u64 {
+		self.area() + other.area() - self.intersection_area(other)
+	}
+	/// Intersection over union, between 0 (disjoint) and 1 (identical).
+	pub fn iou(&self, other: &Rectangle) -> f64 {
+		self.intersection_area(other) as f64 / self.union_area(other) as f64
+	}
+	/// Whether `other` lies entirely within this rectangle.
+	pub fn contains(&self, other: &Rectangle) -> bool {
+		self.intersection(other).as_ref() == Some(other)
+	}
+	pub fn contains_point(&self, x: u32, y: u32) -> bool {
+		self.min.x <= x && x <= self.max.x && self.min.y <= y && y <= self.max.y
+	}
+	/// The center of the region covered by the rectangle.
+	pub fn center(&self) -> (f64, f64) {
+		((self.min.x as f64 + self.max.x as f64 + 1.0) / 2.0, (self.min.y as f64 + self.max.y as f64 + 1.0) / 2.0)
 	}
-	pub fn set_size(&mut self, w: u32, h: u32) -> &mut Rectangle { 
-		self.max.x = Some(self.min.get_x() + w - 1);
-		self.max.y = Some(self.min.get_y() + h - 1);
-		self
+	/// The Euclidean distance between the centers of two rectangles.
+	pub fn center_distance(&self, other: &Rectangle) -> f64 {
+		let ((ax, ay), (bx, by)) = (self.center(), other.center());
+		((ax - bx).powi(2) + (ay - by).powi(2)).sqrt()
+	}
+	/// The rectangle moved by (dx, dy); None if it would leave the positive quadrant.
+	pub fn translate(&self, dx: i64, dy: i64) -> Option<Rectangle> {
+		let shift = |v: u32, d: i64| {
+			let v = v as i64 + d;
+			if v < 0 || v > u32::MAX as i64 { None } else { Some(v as u32) }
+		};
+		match (shift(self.min.x, dx), shift(self.min.y, dy), shift(self.max.x, dx), shift(self.max.y, dy)) {
+			(Some(x0), Some(y0), Some(x1), Some(y1)) => Some(Rectangle::from_corners(x0, y0, x1, y1)),
+			_ => None,
+		}
+	}
+	/// The rectangle in coordinates scaled by `sx` and `sy`, e.g. of a resized page image.
+	/// Edges are rounded to the nearest pixel corner; the result covers at least one pixel.
+	pub fn scale(&self, sx: f64, sy: f64) -> Rectangle {
+		if !(sx > 0.0 && sy > 0.0) { panic!("Scale factors must be positive"); }
+		// Edges are in continuous coordinates, and saturate at the end of the coordinate range
+		let edge = |v: f64, s: f64| (v * s).round().min(u32::MAX as f64) as u32;
+		let (x0, y0) = (edge(self.min.x as f64, sx).min(u32::MAX - 1), edge(self.min.y as f64, sy).min(u32::MAX - 1));
+		let (x1, y1) = (edge(self.max.x as f64 + 1.0, sx).max(x0 + 1), edge(self.max.y as f64 + 1.0, sy).max(y0 + 1));
+		Rectangle::from_corners(x0, y0, x1 - 1, y1 - 1)
 	}
 }
 
 #[test]
 fn test_rectangle_and_point() {
-	let mut a = Rectangle::new();
-	a.set_min(1, 1).set_size(10, 10);
+	let a = Rectangle::from_min_size(1, 1, 10, 10).unwrap();
 	assert_eq!(a.width(), 10);
 	assert_eq!(a.height(), 10);
 	assert_eq!(a.area(), 100);
 }
 
 #[test]
-#[should_panic]
-fn test_bad_rectangle() {
-	let mut a = Rectangle::new();
-	a.set_min(50, 50).set_max(30, 40);
-}
\ No newline at end of file
+fn test_rectangle_conventions() {
+	// A single pixel has its min and max corners on that pixel
+	let pixel = Rectangle::from_min_size(3, 4, 1, 1).unwrap();
+	assert_eq!((pixel.min(), pixel.max()), (Point::new(3, 4), Point::new(3, 4)));
+	assert_eq!((pixel.width(), pixel.height(), pixel.area()), (1, 1, 1));
+	assert_eq!(Rectangle::from_min_max(3, 4, 3, 4), Ok(pixel));
+	// The max corner is inclusive
+	let a = Rectangle::from_min_size(10, 20, 5, 3).unwrap();
+	assert_eq!(a.max(), Point::new(14, 22));
+	assert_eq!(Rectangle::from_min_max(10, 20, 14, 22), Ok(a));
+	assert!(a.contains_point(14, 22) && !a.contains_point(15, 22));
+	// Invalid rectangles cannot be built
+	assert_eq!(Rectangle::from_min_size(3, 4, 0, 1), Err(RectangleError::Empty));
+	assert_eq!(Rectangle::from_min_size(3, 4, 1, 0), Err(RectangleError::Empty));
+	assert_eq!(Rectangle::from_min_max(3, 4, 2, 4), Err(RectangleError::Inverted));
+	assert_eq!(Rectangle::from_min_size(u32::MAX, 0, 2, 1), Err(RectangleError::Overflow));
+	assert_eq!(Rectangle::from_min_max(0, 0, u32::MAX, 0), Err(RectangleError::Overflow));
+	let widest = Rectangle::from_min_size(0, 0, u32::MAX, u32::MAX).unwrap();
+	assert_eq!(widest.area(), u32::MAX as u64 * u32::MAX as u64);
+}
+
+#[test]
+fn test_rectangle_geometry() {
+	use std::collections::HashSet;
+	let rect = |x, y, w, h| Rectangle::from_min_size(x, y, w, h).unwrap();
+	let a = rect(0, 0, 10, 10);
+	let b = rect(5, 5, 10, 10);
+	assert_eq!(a, rect(0, 0, 10, 10));
+	assert_ne!(a, b);
+	assert_eq!(a.intersection(&b), Some(rect(5, 5, 5, 5)));
+	assert_eq!(a.intersection(&rect(10, 0, 5, 5)), None);
+	// Rectangles sharing a row of pixels intersect
+	assert_eq!(a.intersection(&rect(9, 0, 5, 5)), Some(rect(9, 0, 1, 5)));
+	assert_eq!(a.union_area(&b), 175);
+	assert!((a.iou(&b) - 25.0 / 175.0).abs() < 1e-12);
+	assert_eq!(a.iou(&a), 1.0);
+	assert_eq!(a.iou(&rect(20, 20, 1, 1)), 0.0);
+	assert!(a.contains(&rect(2, 3, 8, 7)));
+	assert!(!a.contains(&b));
+	assert!(a.contains_point(9, 9) && !a.contains_point(10, 9));
+	assert_eq!(a.center(), (5.0, 5.0));
+	assert_eq!(a.center_distance(&rect(3, 4, 10, 10)), 5.0);
+	assert_eq!(a.translate(5, 5), Some(b));
+	assert_eq!(b.translate(-5, -5), Some(a));
+	assert_eq!(a.translate(-1, 0), None);
+	assert_eq!(rect(159, 1775, 184, 89).scale(0.5, 0.5), rect(80, 888, 92, 44));
+	assert_eq!(rect(3, 3, 1, 1).scale(0.1, 0.1), rect(0, 0, 1, 1));
+	let set: HashSet<Rectangle> = vec![a, b, a].into_iter().collect();
+	assert_eq!(set.len(), 2);
+}
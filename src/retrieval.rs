@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use tokens::{RelevantToken, TokenId};
+use metrics::Benchmark;
+use metrics::BenchmarkResult;
+use parsers::FileType;
+use collection::Collection;
+use transforms::Transforms;
+use spatial::SpatialIndex;
+use nms::NonMaximumSuppression;
+use cache::match_keys;
+use aggregation::{DocumentRanking, aggregate_relevants, aggregate_results, aggregate_spans};
+
+/// Relevance judgements and retrieval results for a set of queries,
+/// together with the evaluation options and the benchmarks computed on them.
+///
+/// Tokens are either read from files (see `ParserXmlICFHR14`) or added in memory:
+///
+/// ```
+/// use rusteval::RetrievalData;
+/// use rusteval::tokens::{RelevantToken, TokenId};
+///
+/// let mut data = RetrievalData::new();
+/// let mut tok = RelevantToken::new();
+/// tok.set_tokenid(TokenId::NumericId(7));
+/// data.add_relevant_token("query1", tok);
+/// let mut tok = RelevantToken::new();
+/// tok.set_tokenid(TokenId::NumericId(7));
+/// data.add_result_token("query1", tok);
+/// data.evaluate();
+/// let ap = data.benchmark_results()["query1"]["ap"].scalar();
+/// assert_eq!(ap, Some(1.0));
+/// ```
+pub struct RetrievalData {
+	pub(crate) relevant_tokens_filename: 	Option<String>,
+	pub(crate) relevant_tokens: 			Option<HashMap<String, Vec<RelevantToken>>>,
+	/// For queries loaded from an index file, the match keys of their relevant tokens (see `cache::match_keys`).
+	pub(crate) match_keys:					HashMap<String, Vec<(u64, usize)>>,
+	pub(crate) allow_missing_index_source:	bool,
+	pub(crate) result_tokens_filename:		Option<String>,
+	pub(crate) result_tokens: 				Option<HashMap<String, Vec<RelevantToken>>>,
+	pub(crate) benchmark_results:			HashMap<String, HashMap<String, BenchmarkResult>>,
+	pub(crate) depth:						Option<usize>,
+	pub(crate) min_relevance:				Option<f32>,
+	pub(crate) gains:						Option<HashMap<i32, f32>>,
+	pub(crate) query_instances_filename:	Option<String>,
+	pub(crate) query_instances:				HashMap<String, RelevantToken>,
+	pub(crate) selected_queries:			Option<HashSet<String>>,
+	pub(crate) query_groups:				HashMap<String, Vec<String>>,
+	pub(crate) streaming:					bool,
+	pub(crate) keep_query_instances:		bool,
+	pub(crate) keep_top:					Option<usize>,
+	pub(crate) collection:					Option<Collection>,
+	pub(crate) overlap_threshold:			Option<f32>,
+	pub(crate) transforms:					Option<Transforms>,
+	/// The relevant regions of the query being evaluated, indexed for overlap matching.
+	pub(crate) spatial_index:				Option<(String, SpatialIndex)>,
+	pub(crate) nms:							Option<NonMaximumSuppression>,
+	pub(crate) document_ranking:			Option<DocumentRanking>,
+}
+
+impl Default for RetrievalData {
+	fn default() -> RetrievalData { RetrievalData::new() }
+}
+
+impl RetrievalData {
+	pub fn new() -> RetrievalData {
+		RetrievalData {
+			relevant_tokens_filename: None,
+			relevant_tokens: None,
+			match_keys: HashMap::new(),
+			allow_missing_index_source: false,
+			result_tokens_filename: None,
+			result_tokens: None,
+			benchmark_results: HashMap::new(),
+			depth: None,
+			min_relevance: None,
+			gains: None,
+			query_instances_filename: None,
+			query_instances: HashMap::new(),
+			selected_queries: None,
+			query_groups: HashMap::new(),
+			streaming: false,
+			keep_query_instances: false,
+			keep_top: None,
+			collection: None,
+			overlap_threshold: None,
+			transforms: None,
+			spatial_index: None,
+			nms: None,
+			document_ranking: None,
+		}
+	}
+	pub fn set_relevants_filename(&mut self, fname: String) { self.relevant_tokens_filename = Some(fname); }
+	pub fn set_results_filename(&mut self, fname: String) { self.result_tokens_filename = Some(fname); }
+	/// Index files are loaded even if the relevance file they were built from can no longer be found,
+	/// without checking that they are up to date.
+	pub fn set_allow_missing_index_source(&mut self, allow: bool) { self.allow_missing_index_source = allow; }
+	/// Only the first `depth` retrieved tokens of each query are taken into account.
+	pub fn set_depth(&mut self, depth: usize) { self.depth = Some(depth); }
+	/// Ground-truth tokens with relevance below `r` are treated as non-relevant.
+	pub fn set_min_relevance(&mut self, r: f32) { self.min_relevance = Some(r); }
+	/// Relevance grades are mapped to the gains of graded metrics (nDCG) by `gains`, e.g. {0: 0, 1: 1, 2: 3, 3: 7};
+	/// grades missing from `gains`, and tokens without a grade, have their grade or relevance as gain.
+	pub fn set_gains(&mut self, gains: HashMap<i32, f32>) { self.gains = Some(gains); }
+	/// Regions (bounding boxes or polygons) on the same document match when their intersection over union
+	/// is at least `t`; by default, only identical regions match.
+	pub fn set_overlap_threshold(&mut self, t: f32) {
+		if t <= 0.0 || t > 1.0 { panic!("Invalid value for the overlap threshold") }
+		self.overlap_threshold = Some(t);
+	}
+	/// Result lists are filtered by non-maximum suppression before evaluation: regions overlapping a
+	/// better scoring region on the same document with an intersection over union of at least `t` are removed.
+	pub fn set_non_maximum_suppression(&mut self, t: f32) { self.nms = Some(NonMaximumSuppression::new(t)); }
+	pub fn non_maximum_suppression(&self) -> Option<&NonMaximumSuppression> { self.nms.as_ref() }
+	/// Queries are evaluated at document level: their relevant tokens and result lists are replaced by
+	/// the documents they lie on, with result documents ranked by `ranking` (see `aggregation`).
+	pub fn set_document_level(&mut self, ranking: DocumentRanking) { self.document_ranking = Some(ranking); }
+	pub fn document_level(&self) -> Option<DocumentRanking> { self.document_ranking }
+	pub fn set_query_instances_filename(&mut self, fname: String) { self.query_instances_filename = Some(fname); }
+	/// Only queries in `queries` are evaluated; results for any other query are ignored.
+	pub fn set_selected_queries(&mut self, queries: HashSet<String>) { self.selected_queries = Some(queries); }
+	/// Maps each query to the labels of the groups it belongs to, for per-group means.
+	pub fn set_query_groups(&mut self, groups: HashMap<String, Vec<String>>) { self.query_groups = groups; }
+	/// Result files are evaluated one query block at a time and the result tokens are discarded,
+	/// so that only the benchmarks are kept in memory; with `keep_top` = Some(k), the first k
+	/// result tokens of each query are kept for reporting (e.g. `print_document_breakdown`).
+	/// `evaluate` should not be called on streamed results, as they are incomplete.
+	pub fn set_streaming(&mut self, keep_top: Option<usize>) { self.streaming = true; self.keep_top = keep_top; }
+	/// Query instances are kept in the token lists read from files, instead of being excluded,
+	/// so that files can be converted without loss. Result files are still evaluated without them.
+	pub fn set_keep_query_instances(&mut self, keep: bool) { self.keep_query_instances = keep; }
+	/// Tokens read from files are checked against the pages of `collection` (see `Collection::validate`).
+	pub fn set_collection(&mut self, collection: Collection) { self.collection = Some(collection); }
+	pub fn collection(&self) -> Option<&Collection> { self.collection.as_ref() }
+	/// Bounding boxes of result tokens read from files are transformed to the coordinates of the
+	/// relevance judgements, before they are checked against the collection.
+	pub fn set_transforms(&mut self, transforms: Transforms) { self.transforms = Some(transforms); }
+	pub fn transforms(&self) -> Option<&Transforms> { self.transforms.as_ref() }
+	/// Sets the query instance of a query-by-example query; it is excluded from evaluation.
+	pub fn set_query_instance(&mut self, queryname: &str, token: RelevantToken) -> &mut RetrievalData {
+		self.query_instances.insert(String::from(queryname), token); self
+	}
+	/// Appends a token to the relevant tokens of a query.
+	pub fn add_relevant_token(&mut self, queryname: &str, token: RelevantToken) -> &mut RetrievalData {
+		self.match_keys.remove(queryname);
+		self.relevant_tokens.get_or_insert_with(HashMap::new)
+			.entry(String::from(queryname)).or_default()
+			.push(token);
+		self
+	}
+	/// Appends a token to the result list of a query; tokens must be added from best to worst match.
+	pub fn add_result_token(&mut self, queryname: &str, token: RelevantToken) -> &mut RetrievalData {
+		self.result_tokens.get_or_insert_with(HashMap::new)
+			.entry(String::from(queryname)).or_default()
+			.push(token);
+		self
+	}
+	/// Computes all benchmarks for every result list, replacing any previously computed ones.
+	/// Result files are evaluated as they are parsed, so this is only needed for tokens added in memory.
+	pub fn evaluate(&mut self) {
+		self.benchmark_results.clear();
+		let mut result_tokens = self.result_tokens.take();
+		if let Some(ref mut res) = result_tokens {
+			for (queryname, results) in res.iter_mut() {
+				self.evaluate_query(queryname, results);
+			}
+		}
+		self.result_tokens = result_tokens;
+	}
+	/// Removes the query instance (if any) from the result list and the relevant tokens of a query,
+	/// then computes its benchmarks, unless the query has not been selected.
+	/// Suppressed results are removed from the result list first, if non-maximum suppression is set,
+	/// then tokens are aggregated by document for document-level evaluation. When whole lines are
+	/// retrieved, relevant spans are collapsed into their lines, so that each line counts once.
+	/// The relevant tokens are only aggregated for scoring; they are kept as loaded.
+	pub(crate) fn evaluate_query(&mut self, queryname: &str, results: &mut Vec<RelevantToken>) {
+		self.exclude_query_instance(queryname, results);
+		if !self.is_selected(queryname) { return; }
+		if let Some(ref mut nms) = self.nms {
+			nms.apply(queryname, results);
+		}
+		if let Some(ranking) = self.document_ranking {
+			aggregate_results(results, ranking);
+		}
+		let mut loaded = None;
+		if let Some(mut relevants) = self.relevant_tokens.as_mut().and_then( |r| r.remove(queryname) ) {
+			let before = relevants.len();
+			self.exclude_query_instance(queryname, &mut relevants);
+			// The match keys of an index file point into the tokens as stored
+			if relevants.len() != before && self.match_keys.contains_key(queryname) {
+				self.match_keys.insert(String::from(queryname), match_keys(&relevants));
+			}
+			let scored = match self.scoring_relevants(&relevants, results) {
+				Some(scored) => {
+					loaded = Some((relevants, self.match_keys.remove(queryname)));
+					scored
+				},
+				None => relevants,
+			};
+			self.relevant_tokens.as_mut().unwrap().insert(String::from(queryname), scored);
+		}
+		self.store_all_numerical_results(queryname, results);
+		if let Some((relevants, keys)) = loaded {
+			self.relevant_tokens.as_mut().unwrap().insert(String::from(queryname), relevants);
+			if let Some(keys) = keys { self.match_keys.insert(String::from(queryname), keys); }
+		}
+	}
+	/// The relevant tokens that a result list is scored against, if they differ from the loaded ones:
+	/// relevant spans collapsed into their lines when whole lines are retrieved, and tokens aggregated
+	/// by document for document-level evaluation.
+	pub(crate) fn scoring_relevants(&self, relevants: &[RelevantToken], results: &[RelevantToken]) -> Option<Vec<RelevantToken>> {
+		let line_level = results.iter().any( |tok| matches!(tok.id, TokenId::Line(_)) );
+		if !line_level && self.document_ranking.is_none() { return None; }
+		let mut scored = relevants.to_vec();
+		if line_level { aggregate_spans(&mut scored); }
+		if self.document_ranking.is_some() { aggregate_relevants(&mut scored); }
+		Some(scored)
+	}
+	/// Called by the parsers once all tokens of a query block have been read.
+	/// Result boxes are transformed, and tokens checked against the collection, if any. Result lists are evaluated, if relevance judgements have been loaded, and dropped when streaming.
+	pub(crate) fn finish_query_block(&mut self, ft: FileType, queryname: &str, tokens: &mut Vec<RelevantToken>) {
+		if ft == FileType::RelevantTokensFile { self.match_keys.remove(queryname); }
+		if let (FileType::ResultsFile, Some(transforms)) = (ft, self.transforms.as_mut()) {
+			transforms.apply(tokens);
+		}
+		if let Some(ref mut collection) = self.collection {
+			collection.validate(ft, tokens);
+		}
+		match ft {
+			FileType::ResultsFile if self.relevant_tokens.is_some() => {
+				self.evaluate_query(queryname, tokens);
+				if self.streaming {
+					tokens.truncate(self.keep_top.unwrap_or(0));
+					tokens.shrink_to_fit();
+				}
+			},
+			_ if self.keep_query_instances => {},
+			_ => self.exclude_query_instance(queryname, tokens),
+		}
+	}
+	/// Whether a streamed result token following the `kept` tokens of its query block may affect the benchmarks;
+	/// tokens of unselected queries and tokens beyond the depth cut-off need not be stored.
+	pub(crate) fn must_keep_result(&self, queryname: &str, kept: &[RelevantToken], token: &RelevantToken) -> bool {
+		if !self.is_selected(queryname) { return false; }
+		match self.depth {
+			// Suppressed results are removed, and documents ranked, before the cut-off, so all tokens may count
+			Some(_) if self.nms.is_some() || self.document_ranking.is_some() => true,
+			// So may tokens after results removed by the collection or the transforms, or after
+			// several instances of the query removed by overlap
+			Some(_) if self.collection.is_some() || self.transforms.is_some() || self.overlap_threshold.is_some() => true,
+			// A retrieved query instance is removed before the cut-off, so keep one more token
+			Some(d) => kept.len() < d + self.query_instances.contains_key(queryname) as usize
+				// Tokens tied with the last kept one belong to its tie group, which may straddle the cut-off
+				|| (token.get_score().is_some() && kept.last().map( |t| t.get_score() ) == Some(token.get_score())),
+			None => true,
+		}
+	}
+	pub fn relevant_tokens(&self) -> Option<&HashMap<String, Vec<RelevantToken>>> { self.relevant_tokens.as_ref() }
+	pub fn result_tokens(&self) -> Option<&HashMap<String, Vec<RelevantToken>>> { self.result_tokens.as_ref() }
+	/// Computed benchmarks, indexed by query name and then by benchmark name (e.g. "ap").
+	pub fn benchmark_results(&self) -> &HashMap<String, HashMap<String, BenchmarkResult>> { &self.benchmark_results }
+	pub fn is_selected(&self, queryname: &str) -> bool {
+		match self.selected_queries {
+			Some(ref q) => q.contains(queryname),
+			None => true,
+		}
+	}
+	/// All group labels, sorted.
+	pub fn group_labels(&self) -> Vec<String> {
+		let mut labels: Vec<String> = self.query_groups.values()
+			.flat_map( |g| g.iter().cloned() )
+			.collect::<HashSet<String>>()
+			.into_iter()
+			.collect();
+		labels.sort();
+		labels
+	}
+}
+
+#[test]
+fn test_in_memory_evaluation() {
+	use tokens::TokenId;
+	use metrics::MetricMAP;
+	let mut f = RetrievalData::new();
+	for &i in [1, 2, 3].iter() {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::NumericId(i));
+		f.add_relevant_token("q", tok);
+	}
+	for &i in [1, 5, 2, 6].iter() {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::NumericId(i));
+		f.add_result_token("q", tok);
+	}
+	f.evaluate();
+	let ap = f.benchmark_results()["q"]["ap"].scalar().unwrap();
+	assert!((ap - (1.0 + 2.0 / 3.0) / 3.0).abs() < 0.0001);
+	let results = &f.result_tokens().unwrap()["q"];
+	assert_eq!(f.average_precision("q", results).scalar(), Some(ap));
+	assert_eq!(f.benchmark_results()["q"]["precAt5"].scalar(), Some(2.0 / 3.0));
+}
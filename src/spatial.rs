@@ -0,0 +1,135 @@
+//! Spatial index of the relevant regions of a query, for overlap matching.
+//!
+//! With an overlap threshold, a retrieved region can only match relevant regions on the same
+//! document whose bounding box intersects its own. Regions are put in the cells of a uniform grid,
+//! per document, so that these candidates are found without comparing every retrieved region to
+//! every relevant one.
+use std::collections::HashMap;
+
+use rectangles::Rectangle;
+use tokens::{RelevantToken, TokenId};
+
+/// The smallest rectangle containing the region of a token, for bounding boxes and polygons.
+fn extent(tok: &RelevantToken) -> Option<Rectangle> {
+	match tok.id {
+		TokenId::BoundingBox(rect) => Some(rect),
+		TokenId::Polygon(ref poly) => Some(poly.bounding_box()),
+		_ => None,
+	}
+}
+
+/// Regions covering more cells than this are not put in the grid, but checked for every query.
+const MAX_CELLS: u64 = 64;
+
+/// The regions of a document, as their position among the indexed tokens and their extent.
+#[derive(Debug, Default)]
+struct DocumentRegions {
+	regions: Vec<(usize, Rectangle)>,
+	/// The regions (as indices into `regions`) in each cell they cover.
+	grid: HashMap<(u32, u32), Vec<usize>>,
+	/// The regions covering more than `MAX_CELLS` cells.
+	large: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct SpatialIndex {
+	/// Width and height of the grid cells, in pixels.
+	cell: u32,
+	documents: HashMap<Option<String>, DocumentRegions>,
+}
+
+impl SpatialIndex {
+	/// Indexes the bounding boxes and polygons among `tokens`; other tokens are left out.
+	/// Cells are about the size of the regions, so that most regions fall in few cells.
+	pub fn new(tokens: &[RelevantToken]) -> SpatialIndex {
+		let extents: Vec<(usize, Rectangle)> = tokens.iter().enumerate()
+			.filter_map( |(i, tok)| extent(tok).map( |r| (i, r) ) )
+			.collect();
+		let sizes: u64 = extents.iter().map( |&(_, r)| r.width().max(r.height()) as u64 ).sum();
+		let cell = (sizes / extents.len().max(1) as u64).clamp(1, u32::MAX as u64) as u32;
+		let mut index = SpatialIndex { cell, documents: HashMap::new() };
+		for (i, rect) in extents {
+			let doc = index.documents.entry(tokens[i].get_document().cloned()).or_default();
+			let k = doc.regions.len();
+			doc.regions.push((i, rect));
+			let (x0, y0, x1, y1) = SpatialIndex::cells(cell, &rect);
+			if (x1 - x0 + 1) as u64 * (y1 - y0 + 1) as u64 > MAX_CELLS {
+				doc.large.push(k);
+				continue;
+			}
+			for y in y0..=y1 {
+				for x in x0..=x1 { doc.grid.entry((x, y)).or_default().push(k); }
+			}
+		}
+		index
+	}
+	/// The first and last columns and rows of the cells covered by `rect`.
+	fn cells(cell: u32, rect: &Rectangle) -> (u32, u32, u32, u32) {
+		let (min, max) = (rect.min(), rect.max());
+		(min.get_x() / cell, min.get_y() / cell, max.get_x() / cell, max.get_y() / cell)
+	}
+	/// The positions, in increasing order, of the indexed regions on the document of `token` whose
+	/// bounding box intersects that of `token`. None if `token` is neither a bounding box nor a polygon.
+	pub fn candidates(&self, token: &RelevantToken) -> Option<Vec<usize>> {
+		let rect = extent(token)?;
+		let doc = match self.documents.get(&token.get_document().cloned()) {
+			Some(d) => d,
+			None => return Some(Vec::new()),
+		};
+		let (x0, y0, x1, y1) = SpatialIndex::cells(self.cell, &rect);
+		let mut found: Vec<usize> = if (x1 - x0 + 1) as u64 * (y1 - y0 + 1) as u64 > doc.regions.len() as u64 {
+			// Scanning the regions is faster than looking up all the cells of a large query
+			(0..doc.regions.len()).collect()
+		} else {
+			let mut in_cells = doc.large.clone();
+			for y in y0..=y1 {
+				for x in x0..=x1 {
+					if let Some(k) = doc.grid.get(&(x, y)) { in_cells.extend_from_slice(k); }
+				}
+			}
+			in_cells
+		};
+		found.sort_unstable();
+		found.dedup();
+		Some(found.into_iter()
+			.map( |k| doc.regions[k] )
+			.filter( |(_, r)| r.intersection(&rect).is_some() )
+			.map( |(i, _)| i )
+			.collect())
+	}
+}
+
+#[test]
+fn test_spatial_index() {
+	use polygons::Polygon;
+	let token = |document: &str, id: TokenId| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(id).set_document(String::from(document));
+		tok
+	};
+	let rect = |x, y, w, h| TokenId::BoundingBox(Rectangle::from_min_size(x, y, w, h).unwrap());
+	// Pseudo-random boxes on two pages, with a page-wide box and a polygon among them
+	let mut seed: u64 = 42;
+	let mut next = |n: u64| { seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407); ((seed >> 33) % n) as u32 };
+	let mut tokens: Vec<RelevantToken> = (0..500)
+		.map( |i| token(if i % 2 == 0 { "a" } else { "b" }, rect(next(2400), next(3400), 20 + next(300), 20 + next(100))) )
+		.collect();
+	tokens.push(token("a", rect(0, 0, 2480, 3508)));
+	tokens.push(token("b", rect(0, 1000, 2480, 50)));
+	tokens.push(token("b", TokenId::Polygon(Polygon::new(vec![(100, 100), (900, 150), (880, 300), (90, 260)]))));
+	tokens.push(token("a", TokenId::NumericId(7)));
+	let index = SpatialIndex::new(&tokens);
+	let mut queries: Vec<RelevantToken> = (0..200)
+		.map( |i| token(if i % 3 == 0 { "a" } else { "b" }, rect(next(2400), next(3400), 1 + next(300), 1 + next(100))) )
+		.collect();
+	queries.push(token("a", rect(0, 0, 2480, 3508)));
+	for q in &queries {
+		let q_rect = extent(q).unwrap();
+		let expected: Vec<usize> = (0..tokens.len())
+			.filter( |&i| tokens[i].get_document() == q.get_document() && extent(&tokens[i]).is_some_and( |r| r.intersection(&q_rect).is_some() ) )
+			.collect();
+		assert_eq!(index.candidates(q), Some(expected));
+	}
+	assert_eq!(index.candidates(&token("c", rect(0, 0, 100, 100))), Some(vec![]));
+	assert_eq!(index.candidates(&tokens[503]), None);
+}
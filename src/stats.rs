@@ -0,0 +1,230 @@
+//! Summary statistics of a relevance file (`rusteval stats`).
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use metrics::Benchmark;
+use retrieval::RetrievalData;
+use tokens::{RelevantToken, TokenId};
+
+/// Minimum, median, maximum and mean of a set of values.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+	pub min: f64,
+	pub median: f64,
+	pub max: f64,
+	pub mean: f64,
+}
+
+impl Summary {
+	/// Returns None for an empty set of values.
+	pub fn new(values: &mut [f64]) -> Option<Summary> {
+		if values.is_empty() { return None; }
+		values.sort_by( |a, b| a.partial_cmp(b).unwrap() );
+		let n = values.len();
+		let median = if n % 2 == 1 { values[n / 2] } else { (values[n / 2 - 1] + values[n / 2]) / 2.0 };
+		Some(Summary {
+			min: values[0],
+			median,
+			max: values[n - 1],
+			mean: values.iter().sum::<f64>() / n as f64,
+		})
+	}
+	fn to_json(&self) -> String {
+		format!(r#"{{"min": {}, "median": {}, "max": {}, "mean": {}}}"#, self.min, self.median, self.max, self.mean)
+	}
+}
+
+/// Label of the histogram bin holding `n` relevant tokens: "0", "1", "2-3", "4-7", "8-15", ...
+fn histogram_bin(n: usize) -> (usize, String) {
+	if n < 2 { return (n, format!("{}", n)); }
+	let lower = 1 << (usize::BITS - 1 - n.leading_zeros());
+	(lower, format!("{}-{}", lower, 2 * lower - 1))
+}
+
+fn summary_json(s: &Option<Summary>) -> String {
+	match *s {
+		Some(ref s) => s.to_json(),
+		None => String::from("null"),
+	}
+}
+
+fn json_string(s: &str) -> String {
+	let mut out = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
+
+/// Statistics of the relevant tokens of a `RetrievalData`.
+#[derive(Debug)]
+pub struct GroundTruthStats {
+	pub num_queries: usize,
+	pub num_tokens: usize,
+	/// Judged non-relevant tokens (e.g. of grade 0), which are left out of the other statistics.
+	pub num_non_relevant: usize,
+	pub num_documents: usize,
+	pub relevants_per_query: Option<Summary>,
+	/// Number of queries per bin of relevant tokens per query, see `histogram_bin`.
+	pub relevants_histogram: Vec<(String, usize)>,
+	/// Number of relevant tokens per relevance value (or grade), by increasing value.
+	pub relevance_values: Vec<(f32, usize)>,
+	pub num_boxes: usize,
+	pub box_widths: Option<Summary>,
+	pub box_heights: Option<Summary>,
+	/// Queries with at least one transcribed relevant token.
+	pub queries_with_text: usize,
+	pub queries_without_text: usize,
+}
+
+impl GroundTruthStats {
+	pub fn new(data: &RetrievalData) -> GroundTruthStats {
+		let relevant_tokens = data.relevant_tokens()
+			.expect("GroundTruthStats::new: No relevance file has been loaded");
+		let mut per_query = Vec::new();
+		let mut histogram: BTreeMap<usize, (String, usize)> = BTreeMap::new();
+		let mut documents = HashSet::new();
+		let mut relevance_values: Vec<(f32, usize)> = Vec::new();
+		let (mut widths, mut heights) = (Vec::new(), Vec::new());
+		let mut queries_with_text = 0;
+		let mut num_non_relevant = 0;
+		for judged in relevant_tokens.values() {
+			let tokens: Vec<&RelevantToken> = judged.iter().filter( |tok| data.is_relevant(tok) ).collect();
+			num_non_relevant += judged.len() - tokens.len();
+			per_query.push(tokens.len() as f64);
+			let (lower, label) = histogram_bin(tokens.len());
+			histogram.entry(lower).or_insert((label, 0)).1 += 1;
+			if tokens.iter().any( |tok| tok.get_transcription().is_some() ) { queries_with_text += 1; }
+			for tok in tokens {
+				if let Some(d) = tok.get_document() { documents.insert(d.clone()); }
+				let r = tok.get_relevance();
+				match relevance_values.iter_mut().find( |v| v.0 == r ) {
+					Some(v) => v.1 += 1,
+					None => relevance_values.push((r, 1)),
+				}
+				if let TokenId::BoundingBox(ref bb) = tok.id {
+					widths.push(bb.width() as f64);
+					heights.push(bb.height() as f64);
+				}
+			}
+		}
+		relevance_values.sort_by( |a, b| a.0.partial_cmp(&b.0).unwrap() );
+		GroundTruthStats {
+			num_queries: relevant_tokens.len(),
+			num_tokens: per_query.iter().sum::<f64>() as usize,
+			num_non_relevant,
+			num_documents: documents.len(),
+			relevants_per_query: Summary::new(&mut per_query),
+			relevants_histogram: histogram.into_values().collect(),
+			relevance_values,
+			num_boxes: widths.len(),
+			box_widths: Summary::new(&mut widths),
+			box_heights: Summary::new(&mut heights),
+			queries_with_text,
+			queries_without_text: relevant_tokens.len() - queries_with_text,
+		}
+	}
+	pub fn print(&self) {
+		let summary = |s: &Option<Summary>| match *s {
+			Some(ref s) => format!("min {}, median {}, max {}, mean {:.2}", s.min, s.median, s.max, s.mean),
+			None => String::from("-"),
+		};
+		println!("Queries:\t\t\t{}", self.num_queries);
+		println!("Relevant tokens:\t\t{}", self.num_tokens);
+		println!("Judged non-relevant tokens:\t{}", self.num_non_relevant);
+		println!("Documents:\t\t\t{}", self.num_documents);
+		println!("Relevant tokens per query:\t{}", summary(&self.relevants_per_query));
+		println!();
+		println!("RELEVANT TOKENS\tQUERIES");
+		println!("=======================================================================");
+		for &(ref bin, n) in &self.relevants_histogram {
+			println!("{}\t\t{}", bin, n);
+		}
+		println!();
+		println!("RELEVANCE\tTOKENS");
+		println!("=======================================================================");
+		for &(r, n) in &self.relevance_values {
+			println!("{}\t\t{}", r, n);
+		}
+		println!();
+		println!("Bounding boxes:\t\t\t{}", self.num_boxes);
+		println!("Box width:\t\t\t{}", summary(&self.box_widths));
+		println!("Box height:\t\t\t{}", summary(&self.box_heights));
+		println!("Queries with transcriptions:\t{}", self.queries_with_text);
+		println!("Queries without transcriptions:\t{}", self.queries_without_text);
+	}
+	pub fn to_json(&self) -> String {
+		let histogram: Vec<String> = self.relevants_histogram.iter()
+			.map( |&(ref bin, n)| format!(r#"{{"bin": {}, "queries": {}}}"#, json_string(bin), n) )
+			.collect();
+		let relevance: Vec<String> = self.relevance_values.iter()
+			.map( |&(r, n)| format!(r#"{{"relevance": {}, "tokens": {}}}"#, r, n) )
+			.collect();
+		format!(concat!("{{\n",
+			"  \"queries\": {},\n",
+			"  \"relevant_tokens\": {},\n",
+			"  \"non_relevant_tokens\": {},\n",
+			"  \"documents\": {},\n",
+			"  \"relevant_tokens_per_query\": {},\n",
+			"  \"relevant_tokens_histogram\": [{}],\n",
+			"  \"relevance_values\": [{}],\n",
+			"  \"bounding_boxes\": {},\n",
+			"  \"box_width\": {},\n",
+			"  \"box_height\": {},\n",
+			"  \"queries_with_transcriptions\": {},\n",
+			"  \"queries_without_transcriptions\": {}\n",
+			"}}"),
+			self.num_queries, self.num_tokens, self.num_non_relevant, self.num_documents,
+			summary_json(&self.relevants_per_query), histogram.join(", "), relevance.join(", "),
+			self.num_boxes, summary_json(&self.box_widths), summary_json(&self.box_heights),
+			self.queries_with_text, self.queries_without_text)
+	}
+}
+
+#[test]
+fn test_ground_truth_stats() {
+	use parsers::load_fixtures;
+	assert_eq!(histogram_bin(0).1, "0");
+	assert_eq!(histogram_bin(1).1, "1");
+	assert_eq!(histogram_bin(9).1, "8-15");
+	let stats = GroundTruthStats::new(&load_fixtures(false));
+	assert_eq!(stats.num_queries, 2);
+	assert_eq!(stats.num_tokens, 18);
+	assert_eq!(stats.num_non_relevant, 0);
+	assert_eq!(stats.num_documents, 4);
+	assert_eq!(stats.relevants_per_query, Some(Summary { min: 9.0, median: 9.0, max: 9.0, mean: 9.0 }));
+	assert_eq!(stats.relevants_histogram, vec![(String::from("8-15"), 2)]);
+	assert_eq!(stats.num_boxes, 18);
+	assert_eq!(stats.relevance_values, vec![(0.6, 2), (0.7, 2), (0.8, 2), (0.9, 2), (1.0, 10)]);
+	assert_eq!(stats.box_widths.as_ref().unwrap().min, 176.0);
+	assert_eq!(stats.box_heights.as_ref().unwrap().max, 195.0);
+	assert_eq!((stats.queries_with_text, stats.queries_without_text), (0, 2));
+	let json = stats.to_json();
+	assert!(json.starts_with("{") && json.ends_with("}"));
+	assert!(json.contains(r#""relevant_tokens_histogram": [{"bin": "8-15", "queries": 2}]"#));
+	assert_eq!(Summary::new(&mut [4.0, 1.0, 3.0, 2.0]).unwrap().median, 2.5);
+}
+
+#[test]
+fn test_graded_stats() {
+	use parsers::{FileType, ParserTrecEval};
+	// Judgements of grade 0 or below are counted apart from the relevant tokens
+	let mut f = RetrievalData::new();
+	f.set_relevants_filename(String::from("fixtures/GradedQrelsSample.txt"));
+	f.parse_trec_file(FileType::RelevantTokensFile);
+	let stats = GroundTruthStats::new(&f);
+	assert_eq!(stats.num_queries, 1);
+	assert_eq!(stats.num_tokens, 3);
+	assert_eq!(stats.num_non_relevant, 2);
+	assert_eq!(stats.num_documents, 2);
+	assert_eq!(stats.relevants_per_query, Some(Summary { min: 3.0, median: 3.0, max: 3.0, mean: 3.0 }));
+	assert_eq!(stats.relevance_values, vec![(1.0, 1), (2.0, 1), (3.0, 1)]);
+	assert_eq!(stats.num_boxes, 3);
+	assert!(stats.to_json().contains(r#""non_relevant_tokens": 2"#));
+}
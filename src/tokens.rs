@@ -1,17 +1,32 @@
 use rectangles::Rectangle;
+use polygons::Polygon;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum TokenId {
 	NumericId(u16),
 	BoundingBox(Rectangle),
+	StringId(String),
+	/// A polygonal region, e.g. a rotated word box.
+	Polygon(Polygon),
+	/// A text line, by its id on the document, for line-level retrieval.
+	Line(String),
+	/// Part of a text line: the line id and the offsets of its first and last word (or character), inclusive.
+	Span(String, u32, u32),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RelevantToken {
 	pub id: TokenId,    
-	document: Option<String>,
-    transcription: Option<String>,
-	relevance: Option<f32>,
+	pub(crate) document: Option<String>,
+    pub(crate) transcription: Option<String>,
+	pub(crate) relevance: Option<f32>,
+	/// Integer relevance grade, e.g. 0 to 3 in trec qrels; grades of 0 or less are non-relevant by default.
+	pub(crate) grade: Option<i32>,
+    pub(crate) score: Option<f32>,
+}
+
+impl Default for RelevantToken {
+    fn default() -> RelevantToken { RelevantToken::new() }
 }
 
 impl RelevantToken {
@@ -20,38 +35,65 @@ impl RelevantToken {
             id: TokenId::NumericId(0),
             document: None,
             relevance: None,
+            grade: None,
             transcription: None,
+            score: None,
         }
     }
     pub fn set_tokenid(&mut self, i: TokenId) -> &mut RelevantToken {
         match i {
             TokenId::NumericId(j)            => self.id = TokenId::NumericId(j),
             TokenId::BoundingBox(j)          => self.id = TokenId::BoundingBox(j),
+            TokenId::StringId(j)             => self.id = TokenId::StringId(j),
+            TokenId::Polygon(j)              => self.id = TokenId::Polygon(j),
+            TokenId::Line(j)                 => self.id = TokenId::Line(j),
+            TokenId::Span(j, s, e)           => {
+                if s > e { panic!("Span ends before it starts") }
+                self.id = TokenId::Span(j, s, e)
+            },
         }
         self
     }
     pub fn set_document(&mut self, d: String) -> &mut RelevantToken {
         self.document = Some(d); self
     }
+    pub fn get_document(&self) -> Option<&String> {
+        self.document.as_ref()
+    }
     pub fn set_transcription(&mut self, d: String) -> &mut RelevantToken {
         self.transcription = Some(d); self
     }    
+    pub fn get_transcription(&self) -> Option<&String> {
+        self.transcription.as_ref()
+    }
     pub fn set_relevance(&mut self, r: f32) -> &mut RelevantToken {
         if r <= 0.0 || r > 1.0 { panic!("Invalid value for relevance") }
         self.relevance = Some(r); self
     }
+    pub fn set_grade(&mut self, g: i32) -> &mut RelevantToken {
+        self.grade = Some(g); self
+    }
+    pub fn get_grade(&self) -> Option<i32> {
+        self.grade
+    }
+    pub fn set_score(&mut self, s: f32) -> &mut RelevantToken {
+        self.score = Some(s); self
+    }
+    pub fn get_score(&self) -> Option<f32> {
+        self.score
+    }
     pub fn get_relevance(&self) -> f32 {
-        match self.relevance {
-            Some(r) => r,
-            None => 1.0, //this acts as the default relevance value
-        }
+        // Tokens with a grade have the grade as relevance, so that thresholds apply to grades
+        self.relevance.or(self.grade.map( |g| g as f32 )).unwrap_or(1.0) //this acts as the default relevance value
     }
     pub fn print(&self) {
-        println!("RelevantToken with id:{:?}, referring document:{:?}, relevance:{:?}, transcription:{:?}", 
+        println!("RelevantToken with id:{:?}, referring document:{:?}, relevance:{:?}, grade:{:?}, transcription:{:?}, score:{:?}", 
             self.id, 
             self.document, 
             self.relevance,
+            self.grade,
             self.transcription,
+            self.score,
         )
     }
 }
@@ -65,7 +107,7 @@ fn test_relevant_token() {
     let tok1 = tok1;
 
     let mut tok2 = RelevantToken::new();
-    tok2.set_tokenid(TokenId::BoundingBox(Rectangle::new()))
+    tok2.set_tokenid(TokenId::BoundingBox(Rectangle::from_min_size(0, 0, 1, 1).unwrap()))
         .set_relevance(0.8);
     let tok2 = tok2;
 
@@ -0,0 +1,172 @@
+//! Coordinate transforms of result boxes, for runs computed on rescaled or cropped page images.
+//!
+//! A transform maps a coordinate `v` of the run to `v * scale + offset` in the coordinates of the
+//! relevance judgements; e.g. a run on page images downscaled by half has a scale of 2, and a run
+//! on images cropped at (100, 50) an offset of (100, 50). Per-page transforms are read from a
+//! text file with one page per line:
+//!
+//! ```text
+//! # document scale_x scale_y [offset_x offset_y]
+//! 027_029_001 2 2
+//! 071_053_004 2 2 100 50
+//! ```
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use input::open_input;
+use rectangles::Rectangle;
+use tokens::{RelevantToken, TokenId};
+
+/// How transformed box edges are rounded to pixel boundaries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rounding {
+	/// Round each edge to the nearest pixel boundary.
+	Nearest,
+	/// Round edges away from the box, so that it covers the whole transformed region.
+	Outward,
+	/// Round edges into the box, so that it covers only pixels of the transformed region.
+	Inward,
+}
+
+impl Rounding {
+	pub fn from_name(name: &str) -> Option<Rounding> {
+		match name {
+			"nearest" => Some(Rounding::Nearest),
+			"outward" => Some(Rounding::Outward),
+			"inward" => Some(Rounding::Inward),
+			_ => None,
+		}
+	}
+	fn round(&self, a: f64, b: f64) -> (f64, f64) {
+		match *self {
+			Rounding::Nearest => (a.round(), b.round()),
+			Rounding::Outward => (a.floor(), b.ceil()),
+			Rounding::Inward => (a.ceil(), b.floor()),
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+	pub scale: (f64, f64),
+	pub offset: (f64, f64),
+}
+
+impl Default for Transform {
+	fn default() -> Transform { Transform { scale: (1.0, 1.0), offset: (0.0, 0.0) } }
+}
+
+impl Transform {
+	/// Panics unless both scale factors are positive.
+	pub fn new(scale: (f64, f64), offset: (f64, f64)) -> Transform {
+		if !(scale.0 > 0.0 && scale.1 > 0.0) { panic!("Scale factors must be positive"); }
+		Transform { scale, offset }
+	}
+	/// The pixels `v0` to `v1` (inclusive) on one axis, transformed and rounded to pixels `a` to `b`
+	/// (inclusive); at least one pixel is kept. None if the region lies entirely before the origin.
+	fn axis(v0: u32, v1: u32, scale: f64, offset: f64, rounding: Rounding) -> Option<(u32, u32)> {
+		let (a, b) = (v0 as f64 * scale + offset, (v1 as f64 + 1.0) * scale + offset);
+		if b <= 0.0 || a >= u32::MAX as f64 { return None; }
+		let (a, b) = rounding.round(a.max(0.0), b);
+		let b = b.max(a + 1.0).min(u32::MAX as f64);
+		Some((a as u32, b as u32 - 1))
+	}
+	/// The transformed rectangle; None if it lies entirely before the origin.
+	pub fn apply(&self, rect: &Rectangle, rounding: Rounding) -> Option<Rectangle> {
+		let (min, max) = (rect.min(), rect.max());
+		let (x0, x1) = Transform::axis(min.get_x(), max.get_x(), self.scale.0, self.offset.0, rounding)?;
+		let (y0, y1) = Transform::axis(min.get_y(), max.get_y(), self.scale.1, self.offset.1, rounding)?;
+		Rectangle::from_min_max(x0, y0, x1, y1).ok()
+	}
+}
+
+/// The transforms applied to the bounding boxes of a run: a transform for the whole run and/or per page.
+#[derive(Debug)]
+pub struct Transforms {
+	/// Applied to pages without a transform of their own.
+	pub default: Option<Transform>,
+	pub pages: HashMap<String, Transform>,
+	pub rounding: Rounding,
+	/// Number of boxes removed because they were transformed to before the origin.
+	pub removed: usize,
+}
+
+impl Transforms {
+	pub fn new(default: Option<Transform>, rounding: Rounding) -> Transforms {
+		Transforms { default, pages: HashMap::new(), rounding, removed: 0 }
+	}
+	/// Adds the per-page transforms of a file; see the module documentation for its format.
+	pub fn read_file(&mut self, fname: &str) {
+		let f = open_input(fname).expect("Transform file could not be read.");
+		for (n, buffer) in f.lines().enumerate() {
+			let current_line = buffer.unwrap();
+			let fields: Vec<&str> = current_line.split_whitespace().collect();
+			if fields.is_empty() || fields[0].starts_with('#') { continue; }
+			let values: Vec<f64> = fields[1..].iter()
+				.map( |v| v.parse().unwrap_or_else( |_| panic!("Invalid number on line {} of {}", n + 1, fname) ) )
+				.collect();
+			let transform = match values.len() {
+				2 => Transform::new((values[0], values[1]), (0.0, 0.0)),
+				4 => Transform::new((values[0], values[1]), (values[2], values[3])),
+				_ => panic!("Line {} of {} is not a page transform", n + 1, fname),
+			};
+			self.pages.insert(String::from(fields[0]), transform);
+		}
+	}
+	pub fn for_document(&self, document: Option<&str>) -> Option<&Transform> {
+		document.and_then( |d| self.pages.get(d) ).or(self.default.as_ref())
+	}
+	/// Transforms the bounding boxes among `tokens`, removing those transformed to before the origin.
+	pub fn apply(&mut self, tokens: &mut Vec<RelevantToken>) {
+		let before = tokens.len();
+		tokens.retain_mut( |tok| {
+			let transformed = match (self.for_document(tok.get_document().map(String::as_str)), &tok.id) {
+				(Some(t), TokenId::BoundingBox(rect)) => t.apply(rect, self.rounding),
+				_ => return true,
+			};
+			match transformed {
+				Some(rect) => { tok.set_tokenid(TokenId::BoundingBox(rect)); true },
+				None => false,
+			}
+		});
+		self.removed += before - tokens.len();
+	}
+}
+
+#[test]
+fn test_transforms() {
+	let rect = |x, y, w, h| Rectangle::from_min_size(x, y, w, h).unwrap();
+	let half = Transform::new((2.0, 2.0), (0.0, 0.0));
+	assert_eq!(half.apply(&rect(80, 888, 92, 44), Rounding::Nearest), Some(rect(160, 1776, 184, 88)));
+	let cropped = Transform::new((1.0, 1.0), (100.0, -50.0));
+	assert_eq!(cropped.apply(&rect(10, 60, 5, 5), Rounding::Nearest), Some(rect(110, 10, 5, 5)));
+	// Boxes crossing the origin are clipped, boxes before it are removed
+	assert_eq!(cropped.apply(&rect(10, 40, 5, 20), Rounding::Nearest), Some(rect(110, 0, 5, 10)));
+	assert_eq!(cropped.apply(&rect(10, 40, 5, 10), Rounding::Nearest), None);
+	// Pixels 3 to 8 cover the region from 0.75 to 2.25 once scaled by 1/4
+	let quarter = Transform::new((0.25, 0.25), (0.0, 0.0));
+	assert_eq!(quarter.apply(&rect(3, 3, 6, 6), Rounding::Nearest), Some(rect(1, 1, 1, 1)));
+	assert_eq!(quarter.apply(&rect(3, 3, 6, 6), Rounding::Outward), Some(rect(0, 0, 3, 3)));
+	assert_eq!(quarter.apply(&rect(3, 3, 6, 6), Rounding::Inward), Some(rect(1, 1, 1, 1)));
+	// At least one pixel is kept
+	assert_eq!(quarter.apply(&rect(1, 1, 2, 2), Rounding::Inward), Some(rect(1, 1, 1, 1)));
+
+	let mut transforms = Transforms::new(Some(half), Rounding::Nearest);
+	transforms.read_file("fixtures/TransformSample.txt");
+	assert_eq!(transforms.pages.len(), 2);
+	let token = |document: &str, rect: Rectangle| {
+		let mut tok = RelevantToken::new();
+		tok.set_tokenid(TokenId::BoundingBox(rect)).set_document(String::from(document));
+		tok
+	};
+	let mut tokens = vec![
+		token("027_029_001", rect(159, 1775, 184, 89)),
+		token("071_053_004", rect(10, 10, 10, 10)),
+		token("071_053_004", rect(0, 0, 10, 10)),
+		token("096_008_002", rect(46, 303, 110, 69)),
+	];
+	transforms.apply(&mut tokens);
+	let boxes: Vec<Rectangle> = tokens.iter().map( |t| match t.id { TokenId::BoundingBox(r) => r, _ => panic!() } ).collect();
+	assert_eq!(boxes, vec![rect(159, 1775, 184, 89), rect(0, 0, 10, 10), rect(92, 606, 220, 138)]);
+	assert_eq!(transforms.removed, 1);
+}
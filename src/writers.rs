@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use tokens::RelevantToken;
+use tokens::TokenId;
+use parsers::{FileType, decode_docno};
+use retrieval::RetrievalData;
+
+/// Encodes a token as a trec docno; bounding boxes become `document_x_y_w_h`, polygons
+/// `document_poly_x1,y1,x2,y2,...`, lines `document_line_id` and spans `document_line_id:start-end`,
+/// which `decode_docno` reverses.
+pub fn encode_docno(tok: &RelevantToken) -> String {
+	match tok.id {
+		TokenId::BoundingBox(ref bb) => match tok.get_document() {
+			Some(d) => format!("{}_{}_{}_{}_{}", d, bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()),
+			None => format!("{}_{}_{}_{}", bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()),
+		},
+		TokenId::Polygon(ref poly) => {
+			let points: Vec<String> = poly.points().iter().map( |&(x, y)| format!("{},{}", x, y) ).collect();
+			match tok.get_document() {
+				Some(d) => format!("{}_poly_{}", d, points.join(",")),
+				None => format!("poly_{}", points.join(",")),
+			}
+		},
+		TokenId::Line(ref line) => match tok.get_document() {
+			Some(d) => format!("{}_line_{}", d, line),
+			None => format!("line_{}", line),
+		},
+		TokenId::Span(ref line, start, end) => match tok.get_document() {
+			Some(d) => format!("{}_line_{}:{}-{}", d, line, start, end),
+			None => format!("line_{}:{}-{}", line, start, end),
+		},
+		TokenId::NumericId(i) => format!("{}", i),
+		TokenId::StringId(ref s) => s.clone(),
+	}
+}
+
+pub fn xml_escape(s: &str) -> String {
+	s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// The queries of a token map, sorted by name, so that output files are reproducible.
+fn sorted_queries(tokens: &HashMap<String, Vec<RelevantToken>>) -> Vec<(&String, &Vec<RelevantToken>)> {
+	let mut queries: Vec<(&String, &Vec<RelevantToken>)> = tokens.iter().collect();
+	queries.sort_by( |a, b| a.0.cmp(b.0) );
+	queries
+}
+
+fn tokens_to_write(data: &RetrievalData, ft: FileType) -> io::Result<&HashMap<String, Vec<RelevantToken>>> {
+	let tokens = match ft {
+		FileType::RelevantTokensFile => data.relevant_tokens(),
+		FileType::ResultsFile => data.result_tokens(),
+	};
+	tokens.ok_or_else( || io::Error::new(io::ErrorKind::InvalidInput, "No tokens have been loaded") )
+}
+
+pub trait WriterXmlICFHR14 {
+	/// The attributes of a `word` element, starting with `document=`.
+	fn icfhr14_attributes(&self, tok: &RelevantToken) -> io::Result<String>;
+	fn write_icfhr14(&self, ft: FileType, out: &mut dyn Write) -> io::Result<()>;
+}
+
+impl WriterXmlICFHR14 for RetrievalData {
+	fn icfhr14_attributes(&self, tok: &RelevantToken) -> io::Result<String> {
+		// String ids written by encode_docno are turned back into bounding boxes
+		let decoded;
+		let tok = match tok.id {
+			TokenId::BoundingBox(_) | TokenId::Polygon(_) | TokenId::Line(_) | TokenId::Span(..) => tok,
+			_ => { decoded = decode_docno(&encode_docno(tok)); &decoded },
+		};
+		let mut attributes = match (&tok.id, tok.get_document()) {
+			(TokenId::BoundingBox(bb), Some(d)) => format!(r#"document="{}" x="{}" y="{}" width="{}" height="{}""#,
+				xml_escape(d), bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()),
+			// Polygons are written as a PAGE-style list of points
+			(TokenId::Polygon(poly), Some(d)) => format!(r#"document="{}" points="{}""#, xml_escape(d),
+				poly.points().iter().map( |&(x, y)| format!("{},{}", x, y) ).collect::<Vec<String>>().join(" ")),
+			(TokenId::Line(line), Some(d)) => format!(r#"document="{}" line="{}""#, xml_escape(d), xml_escape(line)),
+			(TokenId::Span(line, start, end), Some(d)) => format!(r#"document="{}" line="{}" start="{}" end="{}""#,
+				xml_escape(d), xml_escape(line), start, end),
+			_ => return Err(io::Error::new(io::ErrorKind::InvalidData,
+				format!("Token {} can't be written in the ICFHR'14 format, which requires a document and a region or a line", encode_docno(tok)))),
+		};
+		if let Some(t) = tok.get_transcription() { attributes += &format!(r#" Text="{}""#, xml_escape(t)); }
+		if let Some(r) = tok.relevance { attributes += &format!(r#" Relevance="{}""#, r); }
+		if let Some(g) = tok.get_grade() { attributes += &format!(r#" Grade="{}""#, g); }
+		if let Some(s) = tok.get_score() { attributes += &format!(r#" Score="{}""#, s); }
+		Ok(attributes)
+	}
+	fn write_icfhr14(&self, ft: FileType, out: &mut dyn Write) -> io::Result<()> {
+		let (root, element) = match ft {
+			FileType::RelevantTokensFile => ("GroundTruthRelevanceJudgements", "GTRel"),
+			FileType::ResultsFile => ("RelevanceListings", "Rel"),
+		};
+		writeln!(out, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+		writeln!(out, r#"<{} xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#, root)?;
+		for (queryname, tokens) in sorted_queries(tokens_to_write(self, ft)?) {
+			match self.query_instances.get(queryname) {
+				Some(instance) =>
+					writeln!(out, r#"  <{} queryid="{}" {}>"#, element, xml_escape(queryname), self.icfhr14_attributes(instance)?)?,
+				_ => writeln!(out, r#"  <{} queryid="{}">"#, element, xml_escape(queryname))?,
+			}
+			for tok in tokens {
+				writeln!(out, "    <word {} />", self.icfhr14_attributes(tok)?)?;
+			}
+			writeln!(out, "  </{}>", element)?;
+		}
+		writeln!(out, "</{}>", root)
+	}
+}
+
+pub trait WriterTrecEval {
+	/// Writes a trec qrels or run file. Transcriptions of result tokens, if any, are appended as a last column;
+	/// they are left out of qrels, as trec_eval rejects qrels lines with more than four fields.
+	/// Result tokens without a score are given the similarity -rank, so that their order is kept.
+	fn write_trec(&self, ft: FileType, out: &mut dyn Write) -> io::Result<()>;
+}
+
+impl WriterTrecEval for RetrievalData {
+	fn write_trec(&self, ft: FileType, out: &mut dyn Write) -> io::Result<()> {
+		for (queryname, tokens) in sorted_queries(tokens_to_write(self, ft)?) {
+			for (rank, tok) in tokens.iter().enumerate() {
+				match ft {
+					// Relevances are written with a decimal point, so that they are not read back as grades;
+					// tokens with neither are binary relevant
+					FileType::RelevantTokensFile => match (tok.relevance, tok.get_grade()) {
+						(Some(r), _) => write!(out, "{} 0 {} {:?}", queryname, encode_docno(tok), r)?,
+						(None, Some(g)) => write!(out, "{} 0 {} {}", queryname, encode_docno(tok), g)?,
+						(None, None) => write!(out, "{} 0 {} {:?}", queryname, encode_docno(tok), 1.0f64)?,
+					},
+					FileType::ResultsFile => {
+						let sim = tok.get_score().unwrap_or(0.0 - rank as f32);
+						write!(out, "{} 0 {} {} {} rusteval", queryname, encode_docno(tok), rank + 1, sim)?
+					},
+				}
+				match (ft, tok.get_transcription()) {
+					(FileType::ResultsFile, Some(t)) => writeln!(out, " {}", t)?,
+					_ => writeln!(out)?,
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[test]
+fn test_docno_roundtrip() {
+	use rectangles::Rectangle;
+	let mut tok = RelevantToken::new();
+	let rect = Rectangle::from_min_size(159, 1775, 184, 89).unwrap();
+	tok.set_tokenid(TokenId::BoundingBox(rect)).set_document(String::from("027_029_001"));
+	assert_eq!(encode_docno(&tok), "027_029_001_159_1775_184_89");
+	let decoded = decode_docno(&encode_docno(&tok));
+	assert_eq!(decoded.get_document().map( |d| d.as_str() ), Some("027_029_001"));
+	match decoded.id {
+		TokenId::BoundingBox(ref bb) => assert_eq!((bb.min().get_x(), bb.min().get_y(), bb.width(), bb.height()), (159, 1775, 184, 89)),
+		_ => panic!("Expected a bounding box"),
+	}
+	let poly = decode_docno("027_029_001_poly_163,1770,345,1781,341,1866");
+	assert_eq!(poly.get_document().map( |d| d.as_str() ), Some("027_029_001"));
+	assert_eq!(encode_docno(&poly), "027_029_001_poly_163,1770,345,1781,341,1866");
+	match poly.id {
+		TokenId::Polygon(ref p) => assert_eq!(p.points(), &[(163, 1770), (345, 1781), (341, 1866)]),
+		_ => panic!("Expected a polygon"),
+	}
+	let span = decode_docno("027_029_001_line_l11:3-5");
+	assert_eq!(span.get_document().map( |d| d.as_str() ), Some("027_029_001"));
+	match span.id {
+		TokenId::Span(ref line, start, end) => assert_eq!((line.as_str(), start, end), ("l11", 3, 5)),
+		_ => panic!("Expected a span"),
+	}
+	assert_eq!(encode_docno(&span), "027_029_001_line_l11:3-5");
+	// Line ids may contain colons, as long as they don't end with offsets
+	match decode_docno("line_r1:l2").id {
+		TokenId::Line(ref line) => assert_eq!(line, "r1:l2"),
+		_ => panic!("Expected a line"),
+	}
+	match decode_docno("April_d06-086-09").id {
+		TokenId::StringId(ref s) => assert_eq!(s, "April_d06-086-09"),
+		_ => panic!("Expected a string id"),
+	}
+}
+
+#[test]
+fn test_convert_roundtrip() {
+	use std::env;
+	use std::fs;
+	use parsers::{FileFormat, ParserXmlICFHR14, detect_format, parse_input};
+
+	let dir = env::temp_dir().join(format!("rusteval-convert-test-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+	let inputs = [
+		(FileType::RelevantTokensFile, "fixtures/GroundTruthRelevanceJudgementsQbESample.xml"),
+		(FileType::RelevantTokensFile, "fixtures/GradedQrelsSample.txt"),
+		(FileType::ResultsFile, "fixtures/WordSpottingResultsTiesSample.xml"),
+		(FileType::ResultsFile, "fixtures/QbEResultsSample.xml"),
+	];
+	for &(ft, fixture) in inputs.iter() {
+		let load = |fname: &str| {
+			let mut f = RetrievalData::new();
+			f.set_keep_query_instances(true);
+			match ft {
+				FileType::RelevantTokensFile => f.set_relevants_filename(String::from(fname)),
+				FileType::ResultsFile => f.set_results_filename(String::from(fname)),
+			}
+			parse_input(&mut f, ft, None);
+			f
+		};
+		let original = load(fixture);
+		// Nothing is lost, not even the query instances among the tokens
+		let source = fs::read_to_string(fixture).unwrap();
+		let count = |f: &RetrievalData| tokens_to_write(f, ft).unwrap().values().map( |t| t.len() ).sum::<usize>();
+		let expected = if fixture.ends_with(".xml") { source.matches("<word ").count() } else { source.lines().count() };
+		assert_eq!(count(&original), expected, "{}", fixture);
+		original.write_trec(ft, &mut fs::File::create(path("converted.trec")).unwrap()).unwrap();
+		assert_eq!(detect_format(&path("converted.trec")).unwrap(), (FileFormat::TrecEval, ft));
+		if ft == FileType::ResultsFile {
+			// Ranks are 1-based, as in trec_eval runs
+			let ranks: Vec<String> = fs::read_to_string(path("converted.trec")).unwrap().lines().take(2)
+				.map( |l| String::from(l.split_whitespace().nth(3).unwrap()) ).collect();
+			assert_eq!(ranks, vec!["1", "2"]);
+		}
+		let trec = load(&path("converted.trec"));
+		trec.write_icfhr14(ft, &mut fs::File::create(path("converted.xml")).unwrap()).unwrap();
+		assert_eq!(detect_format(&path("converted.xml")).unwrap(), (FileFormat::XmlICFHR14, ft));
+		let back = load(&path("converted.xml"));
+		let tokens = |f: &RetrievalData| sorted_queries(tokens_to_write(f, ft).unwrap()).into_iter()
+			.flat_map( |(q, toks)| toks.iter().map( move |t|
+				format!("{} {} {:?} {:?} {:?} {:?}", q, encode_docno(t), t.relevance, t.get_grade(), t.get_transcription(), t.get_score()) ) )
+			.collect::<Vec<String>>();
+		assert_eq!(tokens(&original), tokens(&back));
+		// Query instances can only be written in the ICFHR'14 format
+		original.write_icfhr14(ft, &mut fs::File::create(path("direct.xml")).unwrap()).unwrap();
+		let direct = load(&path("direct.xml"));
+		assert_eq!(direct.query_instances.len(), source.matches(r#"queryid="sb0000" document="#).count());
+		assert_eq!(direct.query_instances.len(), original.query_instances.len());
+		assert_eq!(tokens(&direct), tokens(&original));
+	}
+
+	// A token without a relevance value is binary relevant, and must not come back as a grade
+	let mut plain = RetrievalData::new();
+	let tok = plain.parse_tokenstring_fast(r#"document="027_029_001" x="159" y="1775" width="184" height="89""#);
+	plain.add_relevant_token("q", tok);
+	plain.write_trec(FileType::RelevantTokensFile, &mut fs::File::create(path("plain.trec")).unwrap()).unwrap();
+	let mut trec = RetrievalData::new();
+	trec.set_relevants_filename(path("plain.trec"));
+	parse_input(&mut trec, FileType::RelevantTokensFile, None);
+	let mut xml = Vec::new();
+	trec.write_icfhr14(FileType::RelevantTokensFile, &mut xml).unwrap();
+	assert!(!String::from_utf8(xml).unwrap().contains("Grade="));
+	let tok = &trec.relevant_tokens().unwrap()["q"][0];
+	assert_eq!((tok.get_grade(), tok.get_relevance()), (None, 1.0));
+
+	// Transcriptions are left out of qrels, which trec_eval reads as exactly four fields
+	let mut lines = RetrievalData::new();
+	lines.set_relevants_filename(String::from("fixtures/LineRelevanceSample.xml"));
+	parse_input(&mut lines, FileType::RelevantTokensFile, None);
+	let mut qrels = Vec::new();
+	lines.write_trec(FileType::RelevantTokensFile, &mut qrels).unwrap();
+	assert!(String::from_utf8(qrels).unwrap().lines().all( |l| l.split_whitespace().count() == 4 ));
+	// Document names are unescaped when read, as they are escaped when written
+	let mut named = RetrievalData::new();
+	let tok = named.parse_tokenstring_fast(r#"document="p&amp;q&lt;&quot;r&quot;&gt;" x="159" y="1775" width="184" height="89" Relevance="0.5""#);
+	assert_eq!(tok.get_document().map( |d| d.as_str() ), Some(r#"p&q<"r">"#));
+	named.add_relevant_token("q", tok);
+	named.write_icfhr14(FileType::RelevantTokensFile, &mut fs::File::create(path("named.xml")).unwrap()).unwrap();
+	let mut xml = RetrievalData::new();
+	xml.set_relevants_filename(path("named.xml"));
+	parse_input(&mut xml, FileType::RelevantTokensFile, None);
+	xml.write_trec(FileType::RelevantTokensFile, &mut fs::File::create(path("named.trec")).unwrap()).unwrap();
+	let mut trec = RetrievalData::new();
+	trec.set_relevants_filename(path("named.trec"));
+	parse_input(&mut trec, FileType::RelevantTokensFile, None);
+	for f in [&xml, &trec].iter() {
+		assert_eq!(encode_docno(&f.relevant_tokens().unwrap()["q"][0]), encode_docno(&named.relevant_tokens().unwrap()["q"][0]));
+	}
+	fs::remove_dir_all(&dir).unwrap();
+}